@@ -23,6 +23,27 @@ impl system::System for TestSystem {
     }
 }
 
+//sums positions without writing, so nothing but the query walk is measured
+struct SumSystem {}
+impl system::System for SumSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(
+        &self,
+        query_result: &mut query::QueryResult,
+        _world: &world::World,
+    ) {
+        let sum = query_result
+            .iter()
+            .map(|e| e.get::<base_components::Position>().unwrap().x as i64)
+            .sum::<i64>();
+        test::black_box(sum);
+    }
+}
+
 #[bench]
 fn position_map_test(b: &mut Bencher) {
     let mut world = default_world::DefaultWorld::get().build();
@@ -47,3 +68,47 @@ fn position_map_test(b: &mut Bencher) {
             .unwrap();
     })
 }
+
+//without the default hooks, so only storage and change application are measured
+fn storage_bench(
+    b: &mut Bencher,
+    mode: storage::StorageMode,
+    system: impl system::System + 'static,
+) {
+    let mut world = world::WorldBuilder::new().with_storage(mode).build();
+    for i in 0..10000 {
+        world
+            .add_entity()
+            .with(base_components::Position { x: i, y: 0 })
+            .with(base_components::Name {
+                name: "test".to_string(),
+            })
+            .spawn();
+    }
+    let stage1 = stage::StageBuilder::new()
+        .with_system(system)
+        .build();
+    b.iter(|| {
+        world.execute_stage(&stage1);
+    })
+}
+
+#[bench]
+fn sparse_storage_test(b: &mut Bencher) {
+    storage_bench(b, storage::StorageMode::Sparse, TestSystem {});
+}
+
+#[bench]
+fn archetype_storage_test(b: &mut Bencher) {
+    storage_bench(b, storage::StorageMode::Archetype, TestSystem {});
+}
+
+#[bench]
+fn sparse_storage_read_test(b: &mut Bencher) {
+    storage_bench(b, storage::StorageMode::Sparse, SumSystem {});
+}
+
+#[bench]
+fn archetype_storage_read_test(b: &mut Bencher) {
+    storage_bench(b, storage::StorageMode::Archetype, SumSystem {});
+}
@@ -0,0 +1,213 @@
+use hashbrown::HashMap;
+
+use crate::{component, entity_id, query, storage};
+
+//every entity with exactly this set of component types, one column per type
+struct Archetype {
+    types: Vec<component::ComponentTypeId>,
+    entities: Vec<entity_id::EntityId>,
+    columns: Vec<Vec<component::UntypedComponent>>,
+}
+
+impl Archetype {
+    fn new(types: Vec<component::ComponentTypeId>) -> Self {
+        Archetype {
+            columns: types.iter().map(|_| Vec::new()).collect(),
+            entities: Vec::new(),
+            types,
+        }
+    }
+    fn column(&self, tp: component::ComponentTypeId) -> Option<usize> {
+        self.types.binary_search(&tp).ok()
+    }
+    fn matches(&self, query: &query::Query) -> bool {
+        query.components.iter().all(|x| self.column(*x).is_some())
+            && query.without.iter().all(|x| self.column(*x).is_none())
+    }
+    //components must be in the same order as types
+    fn push(
+        &mut self,
+        id: entity_id::EntityId,
+        components: Vec<component::UntypedComponent>,
+    ) -> usize {
+        self.entities.push(id);
+        for (column, component) in self.columns.iter_mut().zip(components) {
+            column.push(component);
+        }
+        self.entities.len() - 1
+    }
+    //swap removes a row, returning its components and the entity moved into its place
+    fn take(
+        &mut self,
+        row: usize,
+    ) -> (
+        Vec<component::UntypedComponent>,
+        Option<entity_id::EntityId>,
+    ) {
+        self.entities.swap_remove(row);
+        let components = self
+            .columns
+            .iter_mut()
+            .map(|x| x.swap_remove(row))
+            .collect();
+        (components, self.entities.get(row).copied())
+    }
+}
+
+pub(crate) struct ArchetypeStorage {
+    archetypes: Vec<Archetype>,
+    archetype_index: HashMap<Vec<component::ComponentTypeId>, usize>,
+    //archetype and row of every entity
+    locations: HashMap<entity_id::EntityId, (usize, usize)>,
+}
+
+impl ArchetypeStorage {
+    pub(crate) fn new() -> Self {
+        ArchetypeStorage {
+            archetypes: Vec::new(),
+            archetype_index: HashMap::new(),
+            locations: HashMap::new(),
+        }
+    }
+    fn get_or_create_archetype(&mut self, types: Vec<component::ComponentTypeId>) -> usize {
+        if let Some(index) = self.archetype_index.get(&types) {
+            return *index;
+        }
+        self.archetypes.push(Archetype::new(types.clone()));
+        self.archetype_index
+            .insert(types, self.archetypes.len() - 1);
+        self.archetypes.len() - 1
+    }
+    //removes an entity's row, keeping the location of the entity swapped into it correct
+    fn take_entity(
+        &mut self,
+        id: entity_id::EntityId,
+    ) -> Option<(usize, Vec<component::UntypedComponent>)> {
+        let (archetype, row) = self.locations.remove(&id)?;
+        let (components, moved) = self.archetypes[archetype].take(row);
+        if let Some(moved) = moved {
+            self.locations.insert(moved, (archetype, row));
+        }
+        Some((archetype, components))
+    }
+    fn place_entity(
+        &mut self,
+        id: entity_id::EntityId,
+        mut components: Vec<component::UntypedComponent>,
+    ) {
+        if components.is_empty() {
+            return;
+        }
+        components.sort_by_key(|x| x.get_type());
+        let archetype =
+            self.get_or_create_archetype(components.iter().map(|x| x.get_type()).collect());
+        let row = self.archetypes[archetype].push(id, components);
+        self.locations.insert(id, (archetype, row));
+    }
+}
+
+impl storage::ComponentStorage for ArchetypeStorage {
+    fn insert(
+        &mut self,
+        component: component::UntypedComponent,
+    ) -> Option<component::UntypedComponent> {
+        let id = component.entity_id();
+        if let Some((archetype, row)) = self.locations.get(&id).copied() {
+            let archetype = &mut self.archetypes[archetype];
+            if let Some(column) = archetype.column(component.get_type()) {
+                return Some(std::mem::replace(
+                    &mut archetype.columns[column][row],
+                    component,
+                ));
+            }
+        }
+        //the entity moves to the archetype with the new component added
+        let mut components = self.take_entity(id).map(|(_, x)| x).unwrap_or_default();
+        components.push(component);
+        self.place_entity(id, components);
+        None
+    }
+    //components of a single entity move it once, to the archetype with all of them added
+    fn insert_all(
+        &mut self,
+        components: Vec<component::UntypedComponent>,
+    ) -> Vec<Option<component::UntypedComponent>> {
+        let id = match components.first() {
+            Some(x) => x.entity_id(),
+            None => return Vec::new(),
+        };
+        if components.len() == 1 || components.iter().any(|x| x.entity_id() != id) {
+            return components.into_iter().map(|x| self.insert(x)).collect();
+        }
+        let mut current = self.take_entity(id).map(|(_, x)| x).unwrap_or_default();
+        let previous = components
+            .into_iter()
+            .map(|component| {
+                match current
+                    .iter()
+                    .position(|x| x.get_type() == component.get_type())
+                {
+                    Some(i) => Some(std::mem::replace(&mut current[i], component)),
+                    None => {
+                        current.push(component);
+                        None
+                    }
+                }
+            })
+            .collect();
+        self.place_entity(id, current);
+        previous
+    }
+    fn remove(
+        &mut self,
+        id: component::ComponentInstanceId,
+    ) -> Option<component::UntypedComponent> {
+        let (archetype, _) = self.locations.get(&id.entity_id())?;
+        self.archetypes[*archetype].column(id.component_type_id())?;
+        let (_, mut components) = self.take_entity(id.entity_id())?;
+        let index = components
+            .iter()
+            .position(|x| x.get_type() == id.component_type_id())?;
+        let removed = components.swap_remove(index);
+        self.place_entity(id.entity_id(), components);
+        Some(removed)
+    }
+    fn get(&self, id: component::ComponentInstanceId) -> Option<&component::UntypedComponent> {
+        let (archetype, row) = self.locations.get(&id.entity_id())?;
+        let archetype = &self.archetypes[*archetype];
+        archetype
+            .column(id.component_type_id())
+            .map(|x| &archetype.columns[x][*row])
+    }
+    fn contains_entity(&self, id: entity_id::EntityId) -> bool {
+        self.locations.contains_key(&id)
+    }
+    fn entity_components(
+        &self,
+        id: entity_id::EntityId,
+    ) -> Option<Box<dyn Iterator<Item = &component::UntypedComponent> + '_>> {
+        let (archetype, row) = *self.locations.get(&id)?;
+        Some(Box::new(
+            self.archetypes[archetype]
+                .columns
+                .iter()
+                .map(move |x| &x[row]),
+        ))
+    }
+    fn entities(&self) -> Box<dyn Iterator<Item = entity_id::EntityId> + '_> {
+        Box::new(self.locations.keys().copied())
+    }
+    fn number_of_entities(&self) -> usize {
+        self.locations.len()
+    }
+    fn query(&self, query: &query::Query, visit: &mut storage::QueryVisitor) {
+        if query.components.is_empty() {
+            return;
+        }
+        for archetype in self.archetypes.iter().filter(|x| x.matches(query)) {
+            for (row, id) in archetype.entities.iter().enumerate() {
+                visit(*id, &mut archetype.columns.iter().map(|x| &x[row]));
+            }
+        }
+    }
+}
@@ -47,18 +47,17 @@ pub(crate) fn changed_children_hook(
             comp2,
             removal_type
             @ (query::ChangeType::RemoveComponent | query::ChangeType::UnloadComponent),
+            ..
         ) => {
             if let Some(comp) = comp2.get::<Children>() {
                 let mut changes = Vec::new();
                 for e in comp.entities.iter() {
-                    changes.push(query::Change(
-                        w.get_component_by_instance_id(
-                            component::ComponentInstanceId::new::<Parent>(*e),
-                        )
-                        .unwrap()
-                        .clone(),
-                        *removal_type,
-                    ));
+                    //the child may already be gone, e.g. when it is unloaded alongside its parent
+                    if let Some(parent) = w.get_component_by_instance_id(
+                        component::ComponentInstanceId::new::<Parent>(*e),
+                    ) {
+                        changes.push(query::Change::new(parent.clone(), *removal_type));
+                    }
                 }
                 changes
             } else {
@@ -68,13 +67,14 @@ pub(crate) fn changed_children_hook(
         query::Change(
             comp,
             query::ChangeType::AddComponent | query::ChangeType::UpdateComponent,
+            ..
         ) => {
             if let Some(comp2) = comp.get::<Children>() {
                 let mut changes = Vec::new();
                 for child in comp2.entities.iter() {
                     //if the child already has a parent, replace it as long as it is incorrect
                     if let Some(child_parent) = w.get_component::<Parent>(*child) && child_parent.entity != comp.entity_id() {
-                        changes.push(query::Change(
+                        changes.push(query::Change::new(
                             component::UntypedComponent::new(
                                 Parent {
                                     entity: comp.id().entity_id(),
@@ -83,10 +83,11 @@ pub(crate) fn changed_children_hook(
                             ),
                             query::ChangeType::UpdateComponent,
                         ));
-                    //if the child doesn't have a parent, add one
-                    } else {
+                    //if the child doesn't have a parent, add one. a child that isn't loaded keeps
+                    //the parent it was stored with instead of becoming a stub entity
+                    } else if w.contains_entity(*child) {
                         //add the parent to the child
-                        changes.push(query::Change(
+                        changes.push(query::Change::new(
                             (Parent {
                                 entity: comp.id().entity_id(),
                             })
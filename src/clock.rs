@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use crate::resource;
+
+//simulation time, advanced once per fixed step by the runner
+#[derive(Clone, Debug)]
+pub struct Clock {
+    tick: u64,
+    elapsed: Duration,
+    timestep: Duration,
+    paused: bool,
+    time_scale: f64,
+}
+
+impl resource::Resource for Clock {}
+
+impl Clock {
+    pub fn new(timestep: Duration) -> Self {
+        Clock {
+            tick: 0,
+            elapsed: Duration::ZERO,
+            timestep,
+            paused: false,
+            time_scale: 1.0,
+        }
+    }
+    //steps per second
+    pub fn with_rate(rate: u32) -> Self {
+        Self::new(Duration::from_secs(1) / rate.max(1))
+    }
+    //number of steps run so far, the current one included
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    //real time between steps
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+    //simulation time that passes each step
+    pub fn delta(&self) -> Duration {
+        self.timestep.mul_f64(self.time_scale)
+    }
+    pub fn delta_seconds(&self) -> f64 {
+        self.delta().as_secs_f64()
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = time_scale.max(0.0);
+    }
+    pub(crate) fn advance(&mut self) {
+        self.tick += 1;
+        self.elapsed += self.delta();
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::with_rate(60)
+    }
+}
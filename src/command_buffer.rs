@@ -0,0 +1,66 @@
+use crate::{
+    component::{self, ComponentType},
+    entity_id, world,
+};
+
+pub enum Command {
+    Despawn(entity_id::EntityId),
+    //despawns the entity and everything under it through Children
+    DespawnRecursive(entity_id::EntityId),
+    Insert(component::UntypedComponent),
+    Remove(component::ComponentInstanceId),
+}
+
+//entity commands recorded by a system and applied to the world after the stage
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+}
+
+impl CommandBuffer {
+    pub fn new() -> Self {
+        CommandBuffer {
+            commands: Vec::new(),
+        }
+    }
+    pub fn push(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+    pub fn despawn(&mut self, id: entity_id::EntityId) {
+        self.push(Command::Despawn(id));
+    }
+    pub fn despawn_recursive(&mut self, id: entity_id::EntityId) {
+        self.push(Command::DespawnRecursive(id));
+    }
+    pub fn insert<T: ComponentType>(&mut self, id: entity_id::EntityId, component: T) {
+        self.push(Command::Insert(component.into_untyped(id)));
+    }
+    pub fn remove<T: ComponentType>(&mut self, id: entity_id::EntityId) {
+        self.push(Command::Remove(component::ComponentInstanceId::new::<T>(
+            id,
+        )));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+    //applies every command, returning the errors of those that failed or were rejected
+    pub(crate) fn apply(self, world: &mut world::World) -> Vec<world::WorldError> {
+        self.commands
+            .into_iter()
+            .filter_map(|command| {
+                match command {
+                    Command::Despawn(id) => world.try_remove_entity(id),
+                    Command::DespawnRecursive(id) => world.remove_entity_recursive(id),
+                    Command::Insert(component) => world.insert_untyped_component(component),
+                    Command::Remove(id) => world.remove_untyped_component(id).map(|_| ()),
+                }
+                .err()
+            })
+            .collect()
+    }
+}
+
+impl Default for CommandBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,16 +1,18 @@
 use std::{
     any::Any,
     borrow::Borrow,
-    fmt::Display,
+    fmt::{Debug, Display},
     ops::{Deref, DerefMut},
     sync::Arc,
 };
 
+use parking_lot::RwLock;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{entity_builder, entity_id, hashing};
 
-#[derive(Eq, Hash, PartialEq, Serialize, Deserialize, Copy, Clone)]
+#[derive(Eq, Hash, PartialEq, Ord, PartialOrd, Serialize, Deserialize, Copy, Clone)]
 pub struct ComponentTypeId(u64);
 
 #[derive(Eq, Hash, PartialEq, Serialize, Deserialize, Copy, Clone)]
@@ -42,6 +44,55 @@ pub const fn type_id_from_str(s: &str) -> ComponentTypeId {
     ComponentTypeId(hashing::string_hash(s))
 }
 
+//names of registered component types, used when displaying a ComponentTypeId
+static TYPE_NAMES: RwLock<Vec<(ComponentTypeId, &'static str)>> =
+    parking_lot::const_rwlock(Vec::new());
+
+//strips the module path, e.g. melon::base_components::Position becomes Position
+fn short_type_name(name: &'static str) -> &'static str {
+    let end = name.find('<').unwrap_or(name.len());
+    match name[..end].rfind("::") {
+        Some(i) => &name[i + 2..],
+        None => name,
+    }
+}
+
+pub(crate) fn register_type_name<T: 'static>() -> &'static str {
+    let name = short_type_name(std::any::type_name::<T>());
+    let id = type_id::<T>();
+    let mut names = TYPE_NAMES.write();
+    if !names.iter().any(|(x, _)| *x == id) {
+        names.push((id, name));
+    }
+    name
+}
+
+//world change ticks at which a component was added and last added or updated
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ComponentTicks {
+    pub added: u64,
+    pub changed: u64,
+    //id of the system whose run made the change, 0 if it was made outside of stages
+    pub(crate) added_by: u64,
+    pub(crate) changed_by: u64,
+}
+
+impl ComponentTicks {
+    pub fn is_added_since(&self, tick: u64) -> bool {
+        self.added > tick
+    }
+    pub fn is_changed_since(&self, tick: u64) -> bool {
+        self.changed > tick
+    }
+    //a system doesn't see its own changes as new on its next run
+    pub(crate) fn is_added_by_other_since(&self, tick: u64, system: u64) -> bool {
+        self.is_added_since(tick) && (system == 0 || self.added_by != system)
+    }
+    pub(crate) fn is_changed_by_other_since(&self, tick: u64, system: u64) -> bool {
+        self.is_changed_since(tick) && (system == 0 || self.changed_by != system)
+    }
+}
+
 impl ComponentTypeId {
     pub fn new_with_number(id: u64) -> Self {
         ComponentTypeId(id)
@@ -65,13 +116,14 @@ pub trait ComponentType:
 
 #[derive(Clone)]
 pub struct UntypedComponent {
-    internal: Arc<UntypedComponentInternal>,
+    internal: Arc<UntypedComponentInternal<dyn Any + Send + Sync>>,
 }
 
-struct UntypedComponentInternal {
+//the data is unsized in place rather than boxed, so a component is a single allocation
+struct UntypedComponentInternal<D: ?Sized> {
     component_type_id: ComponentTypeId,
     instance_id: ComponentInstanceId,
-    data: Box<dyn Any + Send + Sync>,
+    data: D,
 }
 
 impl UntypedComponent {
@@ -98,7 +150,7 @@ impl UntypedComponent {
             internal: Arc::new(UntypedComponentInternal {
                 component_type_id: type_id::<T>(),
                 instance_id: ComponentInstanceId::new::<T>(entity_id),
-                data: Box::new(component),
+                data: component,
             }),
         }
     }
@@ -106,7 +158,16 @@ impl UntypedComponent {
 
 impl Display for ComponentTypeId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "type<{:X}>", self.0)
+        match TYPE_NAMES.read().iter().find(|(x, _)| x == self) {
+            Some((_, name)) => write!(f, "{}", name),
+            None => write!(f, "type<{:X}>", self.0),
+        }
+    }
+}
+
+impl Debug for ComponentTypeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
     }
 }
 
@@ -0,0 +1,183 @@
+use hashbrown::HashMap;
+
+use crate::{
+    component::{self, ComponentType},
+    entity_id, resource, world,
+};
+
+type JSONSerializer =
+    fn(&component::UntypedComponent) -> Result<serde_json::Value, world::WorldError>;
+type JSONDeserializer = fn(
+    serde_json::Value,
+    entity_id::EntityId,
+) -> Result<component::UntypedComponent, world::WorldError>;
+type CBORSerializer = fn(&component::UntypedComponent) -> Result<Vec<u8>, world::WorldError>;
+type CBORDeserializer =
+    fn(&[u8], entity_id::EntityId) -> Result<component::UntypedComponent, world::WorldError>;
+
+struct RegisteredComponent {
+    name: &'static str,
+    to_json: JSONSerializer,
+    from_json: JSONDeserializer,
+    to_cbor: CBORSerializer,
+    from_cbor: CBORDeserializer,
+}
+
+struct RegisteredResource {
+    to_json: fn(&resource::UntypedResource) -> Result<serde_json::Value, world::WorldError>,
+    from_json: fn(serde_json::Value) -> Result<resource::UntypedResource, world::WorldError>,
+    to_cbor: fn(&resource::UntypedResource) -> Result<Vec<u8>, world::WorldError>,
+    from_cbor: fn(&[u8]) -> Result<resource::UntypedResource, world::WorldError>,
+}
+
+//maps component type ids to their names and serialization functions, so untyped components
+//can be written out and read back. resources opted into snapshots are registered here too
+pub struct ComponentRegistry {
+    types: HashMap<component::ComponentTypeId, RegisteredComponent>,
+    resources: HashMap<u64, RegisteredResource>,
+}
+
+fn serialization_error(e: impl std::fmt::Display) -> world::WorldError {
+    world::WorldError::SerializationError(e.to_string())
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        ComponentRegistry {
+            types: HashMap::new(),
+            resources: HashMap::new(),
+        }
+    }
+    pub fn register<T: ComponentType>(&mut self) {
+        let name = component::register_type_name::<T>();
+        self.types.insert(
+            component::type_id::<T>(),
+            RegisteredComponent {
+                name,
+                to_json: |c| {
+                    serde_json::to_value(c.get_unchecked::<T>()).map_err(serialization_error)
+                },
+                from_json: |v, id| {
+                    serde_json::from_value::<T>(v)
+                        .map(|x| x.into_untyped(id))
+                        .map_err(serialization_error)
+                },
+                to_cbor: |c| {
+                    serde_cbor::to_vec(c.get_unchecked::<T>()).map_err(serialization_error)
+                },
+                from_cbor: |bytes, id| {
+                    serde_cbor::from_slice::<T>(bytes)
+                        .map(|x| x.into_untyped(id))
+                        .map_err(serialization_error)
+                },
+            },
+        );
+    }
+    pub(crate) fn register_resource<
+        R: resource::Resource + serde::Serialize + serde::de::DeserializeOwned + 'static,
+    >(
+        &mut self,
+    ) {
+        self.resources.insert(
+            resource::get_resource_id::<R>(),
+            RegisteredResource {
+                to_json: |r| serde_json::to_value(r.get_as::<R>()).map_err(serialization_error),
+                from_json: |v| {
+                    serde_json::from_value::<R>(v)
+                        .map(resource::UntypedResource::new)
+                        .map_err(serialization_error)
+                },
+                to_cbor: |r| serde_cbor::to_vec(r.get_as::<R>()).map_err(serialization_error),
+                from_cbor: |bytes| {
+                    serde_cbor::from_slice::<R>(bytes)
+                        .map(resource::UntypedResource::new)
+                        .map_err(serialization_error)
+                },
+            },
+        );
+    }
+    pub(crate) fn serializable_resources(&self) -> impl Iterator<Item = u64> + '_ {
+        self.resources.keys().copied()
+    }
+    fn get_resource(&self, id: u64) -> Result<&RegisteredResource, world::WorldError> {
+        self.resources
+            .get(&id)
+            .ok_or(world::WorldError::ResourceNotFound)
+    }
+    pub(crate) fn resource_to_json(
+        &self,
+        id: u64,
+        resource: &resource::UntypedResource,
+    ) -> Result<serde_json::Value, world::WorldError> {
+        (self.get_resource(id)?.to_json)(resource)
+    }
+    pub(crate) fn resource_from_json(
+        &self,
+        id: u64,
+        value: serde_json::Value,
+    ) -> Result<resource::UntypedResource, world::WorldError> {
+        (self.get_resource(id)?.from_json)(value)
+    }
+    pub(crate) fn resource_to_cbor(
+        &self,
+        id: u64,
+        resource: &resource::UntypedResource,
+    ) -> Result<Vec<u8>, world::WorldError> {
+        (self.get_resource(id)?.to_cbor)(resource)
+    }
+    pub(crate) fn resource_from_cbor(
+        &self,
+        id: u64,
+        bytes: &[u8],
+    ) -> Result<resource::UntypedResource, world::WorldError> {
+        (self.get_resource(id)?.from_cbor)(bytes)
+    }
+    fn get(
+        &self,
+        id: component::ComponentTypeId,
+    ) -> Result<&RegisteredComponent, world::WorldError> {
+        self.types
+            .get(&id)
+            .ok_or(world::WorldError::ComponentNotRegistered(id))
+    }
+    pub fn is_registered(&self, id: component::ComponentTypeId) -> bool {
+        self.types.contains_key(&id)
+    }
+    pub fn get_name(&self, id: component::ComponentTypeId) -> Option<&'static str> {
+        self.types.get(&id).map(|x| x.name)
+    }
+    pub fn to_json(
+        &self,
+        component: &component::UntypedComponent,
+    ) -> Result<serde_json::Value, world::WorldError> {
+        (self.get(component.get_type())?.to_json)(component)
+    }
+    pub fn from_json(
+        &self,
+        id: component::ComponentTypeId,
+        value: serde_json::Value,
+        entity: entity_id::EntityId,
+    ) -> Result<component::UntypedComponent, world::WorldError> {
+        (self.get(id)?.from_json)(value, entity)
+    }
+    pub fn to_cbor(
+        &self,
+        component: &component::UntypedComponent,
+    ) -> Result<Vec<u8>, world::WorldError> {
+        (self.get(component.get_type())?.to_cbor)(component)
+    }
+    pub fn from_cbor(
+        &self,
+        id: component::ComponentTypeId,
+        bytes: &[u8],
+        entity: entity_id::EntityId,
+    ) -> Result<component::UntypedComponent, world::WorldError> {
+        (self.get(id)?.from_cbor)(bytes, entity)
+    }
+}
+
+impl Default for ComponentRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -7,6 +7,11 @@ pub struct DefaultWorld {}
 impl DefaultWorld {
     pub fn get() -> world::WorldBuilder {
         world::WorldBuilder::new()
+            .with_component::<base_components::Position>()
+            .with_component::<base_components::Name>()
+            .with_component::<base_components::Player>()
+            .with_component::<base_components::Children>()
+            .with_component::<base_components::Parent>()
             .with_resource(position_map::PositionMap::new())
             .with_typed_hook::<base_components::Position>(position_map::position_hook)
             .with_typed_hook::<base_components::Children>(base_components::changed_children_hook)
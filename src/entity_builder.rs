@@ -1,29 +1,47 @@
-use crate::{component, entity_id};
+use crate::{component, entity_id, world};
 
 pub trait SpawnLocation {
-    fn spawn(&mut self, components: Vec<component::UntypedComponent>);
+    //None once the id range is exhausted
+    fn allocate_id(&mut self) -> Option<entity_id::EntityId>;
+    fn spawn(
+        &mut self,
+        components: Vec<component::UntypedComponent>,
+    ) -> Result<(), world::WorldError>;
 }
 pub struct EntityBuilder<'spawner> {
     spawn_location: &'spawner mut dyn SpawnLocation,
-    id: entity_id::EntityId,
+    id: Option<entity_id::EntityId>,
     components: Vec<component::UntypedComponent>,
 }
 
 impl<'spawner> EntityBuilder<'spawner> {
     pub fn new(spawn_location: &'spawner mut dyn SpawnLocation) -> Self {
         EntityBuilder {
+            id: spawn_location.allocate_id(),
             spawn_location,
-            id: entity_id::EntityId::new(),
             components: Vec::new(),
         }
     }
     pub fn with<T: component::ComponentType>(mut self, component: T) -> Self {
-        self.components
-            .push(component::UntypedComponent::new(component, self.id));
+        if let Some(id) = self.id {
+            self.components
+                .push(component::UntypedComponent::new(component, id));
+        }
         self
     }
+    //rejected spawns are logged. panics if the id range is exhausted, try_spawn reports that
     pub fn spawn(self) -> entity_id::EntityId {
-        self.spawn_location.spawn(self.components);
-        self.id
+        let id = self.id.expect("entity id range exhausted");
+        if let Err(err) = self.try_spawn() {
+            tracing::warn!("failed to spawn {}: {:?}", id, err);
+        }
+        id
+    }
+    //fails if a validator rejects one of the components or no id is left, in which case
+    //nothing is spawned
+    pub fn try_spawn(self) -> Result<entity_id::EntityId, world::WorldError> {
+        let id = self.id.ok_or(world::WorldError::EntityRangeExhausted)?;
+        self.spawn_location.spawn(self.components)?;
+        Ok(id)
     }
 }
@@ -1,8 +1,8 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, ops::Range};
 
-use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+//the upper 32 bits are the slot index, the lower 32 bits the generation of that slot
 #[derive(Default, Serialize, Deserialize, Clone, PartialEq, Debug, Eq, Hash, Copy)]
 pub struct EntityId(u64);
 
@@ -16,11 +16,113 @@ impl EntityId {
     pub fn new_with_number(id: u64) -> Self {
         EntityId(id)
     }
-    pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
-        EntityId(rng.gen())
+    pub fn from_parts(index: u32, generation: u32) -> Self {
+        EntityId((index as u64) << 32 | generation as u64)
     }
     pub fn id(&self) -> u64 {
         self.0
     }
+    pub fn index(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+    pub fn generation(&self) -> u32 {
+        self.0 as u32
+    }
+}
+
+//hands out entity ids from a range of slot indexes. a despawned slot is reused with its
+//generation bumped, so ids held for the old entity no longer match
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EntityAllocator {
+    range: Range<u32>,
+    next: u32,
+    free: Vec<(u32, u32)>,
+    alive: HashMap<u32, u32>,
+}
+
+impl EntityAllocator {
+    pub fn new() -> Self {
+        Self::with_range(0..u32::MAX)
+    }
+    //only allocates slot indexes in the range, so worlds given disjoint ranges never collide
+    pub fn with_range(range: Range<u32>) -> Self {
+        EntityAllocator {
+            next: range.start,
+            range,
+            free: Vec::new(),
+            alive: HashMap::new(),
+        }
+    }
+    pub fn allocate(&mut self) -> Option<EntityId> {
+        if let Some((index, generation)) = self.free.pop() {
+            self.alive.insert(index, generation);
+            return Some(EntityId::from_parts(index, generation));
+        }
+        //skip slots claimed by entities that came from elsewhere
+        while self.next < self.range.end && self.alive.contains_key(&self.next) {
+            self.next += 1;
+        }
+        if self.next >= self.range.end {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        self.alive.insert(index, 0);
+        Some(EntityId::from_parts(index, 0))
+    }
+    //marks an id allocated elsewhere, e.g. loaded from storage, as alive. returns false and
+    //leaves the allocator alone if the id conflicts
+    pub fn claim(&mut self, id: EntityId) -> bool {
+        if self.conflicts(id) {
+            return false;
+        }
+        if !self.is_alive(id) {
+            self.free.retain(|(index, _)| *index != id.index());
+            self.alive.insert(id.index(), id.generation());
+        }
+        true
+    }
+    //whether another generation of the id's slot is alive, or the slot was freed after the
+    //id's generation and so the id refers to a despawned entity
+    pub fn conflicts(&self, id: EntityId) -> bool {
+        match self.alive.get(&id.index()) {
+            Some(generation) => *generation != id.generation(),
+            None => self
+                .free
+                .iter()
+                .any(|(index, generation)| *index == id.index() && id.generation() < *generation),
+        }
+    }
+    pub fn free(&mut self, id: EntityId) {
+        if self.is_alive(id) {
+            self.alive.remove(&id.index());
+            if self.range.contains(&id.index()) {
+                self.free
+                    .push((id.index(), id.generation().wrapping_add(1)));
+            }
+        }
+    }
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.alive.get(&id.index()) == Some(&id.generation())
+    }
+    //gives up the top len unused indexes of the range, e.g. to hand to another shard
+    pub fn reserve_range(&mut self, len: u32) -> Option<Range<u32>> {
+        let start = self.range.end.checked_sub(len)?;
+        if start < self.next
+            || self
+                .alive
+                .keys()
+                .any(|x| (start..self.range.end).contains(x))
+        {
+            return None;
+        }
+        self.range.end = start;
+        Some(start..start + len)
+    }
+}
+
+impl Default for EntityAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
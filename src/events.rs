@@ -0,0 +1,76 @@
+use crate::resource;
+
+//events sent during the current and the previous tick. sent events can be read until the
+//end of the tick after the one they were sent in
+pub struct Events<E> {
+    previous: Vec<(u64, E)>,
+    current: Vec<(u64, E)>,
+    next_id: u64,
+}
+
+impl<E: Send + Sync + 'static> resource::Resource for Events<E> {}
+
+impl<E> Events<E> {
+    pub fn new() -> Self {
+        Events {
+            previous: Vec::new(),
+            current: Vec::new(),
+            next_id: 0,
+        }
+    }
+    pub fn send(&mut self, event: E) {
+        self.current.push((self.next_id, event));
+        self.next_id += 1;
+    }
+    //every event of the previous and current tick, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &E> {
+        self.previous.iter().chain(&self.current).map(|(_, x)| x)
+    }
+    //the events the reader hasn't seen yet
+    pub fn read<'a>(&'a self, reader: &mut EventReader<E>) -> impl Iterator<Item = &'a E> {
+        let from = reader.next;
+        reader.next = self.next_id;
+        self.previous
+            .iter()
+            .chain(&self.current)
+            .filter(move |(id, _)| *id >= from)
+            .map(|(_, x)| x)
+    }
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    //drops the previous tick's events and starts a new tick
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//remembers which events of one type have been read, e.g. by a system between ticks
+pub struct EventReader<E> {
+    next: u64,
+    event: std::marker::PhantomData<fn() -> E>,
+}
+
+impl<E> EventReader<E> {
+    pub fn new() -> Self {
+        EventReader {
+            next: 0,
+            event: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
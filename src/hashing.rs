@@ -3,3 +3,11 @@ use const_fnv1a_hash::fnv1a_hash_64;
 pub(crate) const fn string_hash(input: &str) -> u64 {
     fnv1a_hash_64(input.as_bytes(), None)
 }
+
+pub(crate) fn combine_hash(values: &[u64]) -> u64 {
+    let bytes = values
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect::<Vec<_>>();
+    fnv1a_hash_64(&bytes, None)
+}
@@ -1,46 +1,161 @@
 use crate::{component, entity_id, query, resource_writer, world};
 
 pub(crate) struct ChangeHook {
-    closure: HookLambda,
+    kind: HookKind,
     component_type: Option<component::ComponentTypeId>,
+    //hooks with a higher priority run first, equal ones in the order they were added
+    priority: i32,
+    name: &'static str,
+}
+pub type HookLambda = Box<
+    dyn Fn(
+            &query::Change,
+            &world::World,
+            &mut resource_writer::ResourceWriter,
+        ) -> Vec<query::Change>
+        + Send
+        + Sync,
+>;
+
+//a hook that keeps state between changes, e.g. a counter or a channel
+pub trait Hook: Send + Sync {
+    fn execute(
+        &mut self,
+        change: &query::Change,
+        world: &world::World,
+        command: &mut resource_writer::ResourceWriter,
+    ) -> Vec<query::Change>;
+    fn priority(&self) -> i32 {
+        0
+    }
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+enum HookKind {
+    Closure(HookLambda),
+    Stateful(parking_lot::Mutex<Box<dyn Hook>>),
 }
-pub type HookLambda =
-    fn(&query::Change, &world::World, &mut resource_writer::ResourceWriter) -> Vec<query::Change>;
 
 impl ChangeHook {
     pub fn new(closure: HookLambda) -> Self {
         Self {
-            closure,
+            kind: HookKind::Closure(closure),
             component_type: None,
+            priority: 0,
+            name: "hook",
         }
     }
     pub fn new_typed<T: component::ComponentType>(closure: HookLambda) -> Self {
         Self {
-            closure,
+            kind: HookKind::Closure(closure),
             component_type: Some(component::type_id::<T>()),
+            priority: 0,
+            name: "hook",
         }
     }
+    pub fn new_stateful(
+        hook: Box<dyn Hook>,
+        component_type: Option<component::ComponentTypeId>,
+    ) -> Self {
+        Self {
+            priority: hook.priority(),
+            name: hook.name(),
+            kind: HookKind::Stateful(parking_lot::Mutex::new(hook)),
+            component_type,
+        }
+    }
+    pub(crate) fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+    pub(crate) fn priority(&self) -> i32 {
+        self.priority
+    }
+    //shown when hooks keep causing changes past the depth limit
+    pub(crate) fn with_name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+    pub(crate) fn name(&self) -> &'static str {
+        self.name
+    }
     pub(crate) fn execute(
         &self,
         change: &query::Change,
         world: &world::World,
         command: &mut resource_writer::ResourceWriter,
     ) -> Vec<query::Change> {
-        (self.closure)(change, world, command)
+        match &self.kind {
+            HookKind::Closure(closure) => closure(change, world, command),
+            HookKind::Stateful(hook) => hook.lock().execute(change, world, command),
+        }
     }
     pub(crate) fn get_type(&self) -> Option<component::ComponentTypeId> {
         self.component_type
     }
 }
 
+//checks a change before it is applied, returning it as is, a rewritten change, or an
+//error rejecting it
+pub type ValidatorLambda = Box<
+    dyn Fn(query::Change, &world::World) -> Result<query::Change, world::WorldError> + Send + Sync,
+>;
+
+pub(crate) struct ChangeValidator {
+    closure: ValidatorLambda,
+    component_type: Option<component::ComponentTypeId>,
+}
+
+impl ChangeValidator {
+    pub fn new(closure: ValidatorLambda) -> Self {
+        Self {
+            closure,
+            component_type: None,
+        }
+    }
+    pub fn new_typed<T: component::ComponentType>(closure: ValidatorLambda) -> Self {
+        Self {
+            closure,
+            component_type: Some(component::type_id::<T>()),
+        }
+    }
+    pub(crate) fn validate(
+        &self,
+        change: query::Change,
+        world: &world::World,
+    ) -> Result<query::Change, world::WorldError> {
+        match self.component_type {
+            Some(tp) if change.0.get_type() != tp => Ok(change),
+            _ => (self.closure)(change, world),
+        }
+    }
+}
+
+//an error keeps the entities in memory, see World::unload
 pub trait Unloader: Send + Sync {
-    fn hook(&self, component: Vec<component::UntypedComponent>, world: &world::World);
+    fn hook(
+        &self,
+        component: Vec<component::UntypedComponent>,
+        world: &world::World,
+    ) -> Result<(), world::WorldError>;
 }
 
+//an error aborts the load, nothing is added to the world
 pub trait Loader: Send + Sync {
     fn load(
         &self,
         id: Vec<entity_id::EntityId>,
         world: &world::World,
-    ) -> Vec<component::UntypedComponent>;
+    ) -> Result<Vec<component::UntypedComponent>, world::WorldError>;
+    //called once the world holds the loaded entities, stores drop their copies here so a
+    //despawned entity can't be loaded again
+    fn forget(
+        &self,
+        _id: Vec<entity_id::EntityId>,
+        _world: &world::World,
+    ) -> Result<(), world::WorldError> {
+        Ok(())
+    }
 }
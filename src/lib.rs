@@ -4,20 +4,32 @@
 #![feature(let_chains)]
 extern crate test;
 pub mod archetype;
+pub mod archetype_storage;
 pub mod base_components;
+pub mod clock;
+pub mod command_buffer;
 pub mod component;
+pub mod component_registry;
 pub mod default_world;
 pub mod entity_builder;
 pub mod entity_id;
+pub mod events;
 pub mod hashing;
 pub mod hook;
 pub mod lore;
+pub mod observer;
 pub mod position_map;
 pub mod query;
 pub mod resource;
 pub mod resource_writer;
+pub mod runner;
+pub mod schedule;
+pub mod sled_store;
+pub mod snapshot;
 pub mod stage;
+pub mod storage;
 pub mod system;
+pub mod typed_query;
 pub mod world;
 use mimalloc::MiMalloc;
 
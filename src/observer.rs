@@ -0,0 +1,91 @@
+use std::sync::mpsc;
+
+use hashbrown::HashSet;
+
+use crate::{component, query};
+
+//an applied change as seen by subscribers
+#[derive(Clone)]
+pub struct ChangeEvent {
+    pub component: component::UntypedComponent,
+    pub change_type: query::ChangeType,
+    //the value an UpdateComponent change replaced
+    pub previous: Option<component::UntypedComponent>,
+}
+
+//which changes a subscriber receives, every change unless component types are given
+#[derive(Clone, Default)]
+pub struct ChangeFilter {
+    components: Option<HashSet<component::ComponentTypeId>>,
+}
+
+impl ChangeFilter {
+    pub fn new() -> Self {
+        ChangeFilter { components: None }
+    }
+    pub fn with<T: component::ComponentType>(self) -> Self {
+        self.with_type(component::type_id::<T>())
+    }
+    pub fn with_type(mut self, tp: component::ComponentTypeId) -> Self {
+        self.components.get_or_insert_with(HashSet::new).insert(tp);
+        self
+    }
+    pub fn matches(&self, tp: component::ComponentTypeId) -> bool {
+        self.components.as_ref().is_none_or(|x| x.contains(&tp))
+    }
+}
+
+pub(crate) struct Subscriber {
+    filter: ChangeFilter,
+    sender: mpsc::Sender<ChangeEvent>,
+}
+
+//changes applied since the last flush, and the subscribers they go to
+#[derive(Default)]
+pub(crate) struct Observers {
+    subscribers: Vec<Subscriber>,
+    pending: Vec<ChangeEvent>,
+}
+
+impl Observers {
+    pub(crate) fn subscribe(&mut self, filter: ChangeFilter) -> mpsc::Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(Subscriber { filter, sender });
+        receiver
+    }
+    pub(crate) fn record(
+        &mut self,
+        component: &component::UntypedComponent,
+        change_type: query::ChangeType,
+        previous: Option<component::UntypedComponent>,
+    ) {
+        if self
+            .subscribers
+            .iter()
+            .any(|x| x.filter.matches(component.get_type()))
+        {
+            self.pending.push(ChangeEvent {
+                component: component.clone(),
+                change_type,
+                previous,
+            });
+        }
+    }
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.len()
+    }
+    //forgets changes recorded after pending() returned len, e.g. because they were rolled back
+    pub(crate) fn discard(&mut self, len: usize) {
+        self.pending.truncate(len);
+    }
+    //sends the pending changes, dropping subscribers whose receiver is gone
+    pub(crate) fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.subscribers.retain(|subscriber| {
+            pending
+                .iter()
+                .filter(|x| subscriber.filter.matches(x.component.get_type()))
+                .all(|x| subscriber.sender.send(x.clone()).is_ok())
+        });
+    }
+}
@@ -3,39 +3,22 @@ use crate::{
     entity_id::{self, EntityId},
     query, resource, resource_writer, world,
 };
-use hashbrown::HashMap;
 use rtree_rs;
+//entities by position. hooks remove old positions using the value each change replaces
 pub struct PositionMap {
-    entity_to_position: HashMap<entity_id::EntityId, [i32; 2]>,
     map: rtree_rs::RTree<2, i32, EntityId>,
 }
 
 impl PositionMap {
     pub fn new() -> Self {
         let rtree = rtree_rs::RTree::new();
-        PositionMap {
-            entity_to_position: HashMap::new(),
-            map: rtree,
-        }
+        PositionMap { map: rtree }
     }
     fn insert(&mut self, id: entity_id::EntityId, position: [i32; 2]) {
         self.map.insert(rtree_rs::Rect::new_point(position), id);
-        self.entity_to_position.insert(id, position);
-    }
-    fn update(&mut self, id: entity_id::EntityId, position: [i32; 2]) {
-        if let Some(old_position) = self.entity_to_position.get(&id) {
-            self.map
-                .remove(rtree_rs::Rect::new_point(*old_position), &id);
-            self.map.insert(rtree_rs::Rect::new_point(position), id);
-            self.entity_to_position.insert(id, position);
-        } else {
-            self.insert(id, position);
-        }
     }
-    fn remove(&mut self, id: entity_id::EntityId) {
-        let position = self.entity_to_position.get(&id).unwrap();
-        self.map.remove(rtree_rs::Rect::new_point(*position), &id);
-        self.entity_to_position.remove(&id);
+    fn remove(&mut self, id: entity_id::EntityId, position: [i32; 2]) {
+        self.map.remove(rtree_rs::Rect::new_point(position), &id);
     }
     pub fn get_nearest(
         &self,
@@ -63,35 +46,24 @@ pub fn position_hook(
     _: &world::World,
     c: &mut resource_writer::ResourceWriter,
 ) -> Vec<query::Change> {
-    //println!("size {}", position_map.map.lock().unwrap().len());
-    match change {
-        query::Change(comp, query::ChangeType::AddComponent) => {
-            if let Some(position) = comp.get::<base_components::Position>() {
-                let x = comp.id().entity_id();
-                let y = [position.x, position.y];
-                c.write_resource(move |position_map: &mut PositionMap| {
-                    position_map.insert(x, y);
-                });
-            }
+    let id = change.0.entity_id();
+    let point = |x: &base_components::Position| [x.x, x.y];
+    let previous = change.previous::<base_components::Position>().map(point);
+    let current = match change.1 {
+        query::ChangeType::AddComponent | query::ChangeType::UpdateComponent => {
+            change.get::<base_components::Position>().map(point)
         }
-        query::Change(
-            comp,
-            query::ChangeType::RemoveComponent | query::ChangeType::UnloadComponent,
-        ) => {
-            let entity_to_be_removed = comp.id().entity_id();
-            c.write_resource(move |position_map: &mut PositionMap| {
-                position_map.remove(entity_to_be_removed);
-            });
-        }
-        query::Change(comp, query::ChangeType::UpdateComponent) => {
-            if let Some(position) = comp.get::<base_components::Position>() {
-                let x = comp.id().entity_id();
-                let y = [position.x, position.y];
-                c.write_resource(move |position_map: &mut PositionMap| {
-                    position_map.update(x, y);
-                });
+        query::ChangeType::RemoveComponent | query::ChangeType::UnloadComponent => None,
+    };
+    if previous != current {
+        c.write_resource(move |position_map: &mut PositionMap| {
+            if let Some(previous) = previous {
+                position_map.remove(id, previous);
             }
-        }
+            if let Some(current) = current {
+                position_map.insert(id, current);
+            }
+        });
     }
     Vec::new()
 }
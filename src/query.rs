@@ -1,15 +1,69 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
 
 use hashbrown::{HashMap, HashSet};
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, SeedableRng};
 
 use crate::{
+    command_buffer,
     component::{self, ComponentType, ComponentTypeId, TypedComponent, UntypedComponent},
-    entity_builder, entity_id, resource,
+    entity_builder, entity_id, events, resource,
     resource_writer::{self},
+    typed_query, world,
 };
 
 pub struct Query {
+    //required components
     pub components: HashSet<component::ComponentTypeId>,
+    pub without: HashSet<component::ComponentTypeId>,
+    pub optional: HashSet<component::ComponentTypeId>,
+    //required components that must have been added or changed since the system last ran
+    pub added: HashSet<component::ComponentTypeId>,
+    pub changed: HashSet<component::ComponentTypeId>,
+    //every fetched component is either only read or possibly written by the system
+    pub reads: HashSet<component::ComponentTypeId>,
+    pub writes: HashSet<component::ComponentTypeId>,
+    //fetch every component of matched entities instead of only the queried ones
+    pub fetch_all: bool,
+}
+
+impl Query {
+    pub fn is_change_filtered(&self) -> bool {
+        !self.added.is_empty() || !self.changed.is_empty()
+    }
+    //component types one query may write while the other reads or writes them.
+    //fetch_all queries may write anything the other fetches
+    pub fn conflicts(&self, other: &Query) -> Vec<component::ComponentTypeId> {
+        let fetched = |x: &Query| x.reads.union(&x.writes).copied().collect::<HashSet<_>>();
+        let (ours, theirs) = (fetched(self), fetched(other));
+        let mut conflicts = if self.fetch_all && other.fetch_all {
+            ours.union(&theirs).copied().collect::<Vec<_>>()
+        } else if self.fetch_all {
+            theirs.into_iter().collect()
+        } else if other.fetch_all {
+            ours.into_iter().collect()
+        } else {
+            self.writes
+                .intersection(&theirs)
+                .chain(other.writes.intersection(&ours))
+                .copied()
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect()
+        };
+        conflicts.sort();
+        conflicts
+    }
+    pub fn conflicts_with(&self, other: &Query) -> bool {
+        (self.fetch_all && other.fetch_all) || !self.conflicts(other).is_empty()
+    }
+    //whether matched entities' T ends up in the query result
+    pub fn fetches(&self, tp: component::ComponentTypeId) -> bool {
+        self.fetch_all || self.components.contains(&tp) || self.optional.contains(&tp)
+    }
 }
 
 pub struct QueryBuilder {
@@ -21,13 +75,66 @@ impl QueryBuilder {
         QueryBuilder {
             query: Query {
                 components: HashSet::new(),
+                without: HashSet::new(),
+                optional: HashSet::new(),
+                added: HashSet::new(),
+                changed: HashSet::new(),
+                reads: HashSet::new(),
+                writes: HashSet::new(),
+                fetch_all: false,
             },
         }
     }
+    //requires T, which the system may write
     pub fn with<T: ComponentType>(mut self) -> Self {
         self.query.components.insert(component::type_id::<T>());
+        self.write_access(component::type_id::<T>())
+    }
+    //requires T, which the system only reads
+    pub fn read<T: ComponentType>(mut self) -> Self {
+        self.query.components.insert(component::type_id::<T>());
+        self.read_access(component::type_id::<T>())
+    }
+    //only matches entities that don't have T
+    pub fn without<T: ComponentType>(mut self) -> Self {
+        self.query.without.insert(component::type_id::<T>());
         self
     }
+    //fetches T for matched entities that have it, without requiring it
+    pub fn optional<T: ComponentType>(mut self) -> Self {
+        self.query.optional.insert(component::type_id::<T>());
+        self.write_access(component::type_id::<T>())
+    }
+    pub fn optional_read<T: ComponentType>(mut self) -> Self {
+        self.query.optional.insert(component::type_id::<T>());
+        self.read_access(component::type_id::<T>())
+    }
+    //fetches every component of matched entities, all of which the system may write
+    pub fn fetch_all(mut self) -> Self {
+        self.query.fetch_all = true;
+        self
+    }
+    fn read_access(mut self, tp: ComponentTypeId) -> Self {
+        if !self.query.writes.contains(&tp) {
+            self.query.reads.insert(tp);
+        }
+        self
+    }
+    fn write_access(mut self, tp: ComponentTypeId) -> Self {
+        self.query.reads.remove(&tp);
+        self.query.writes.insert(tp);
+        self
+    }
+    //only matches entities whose T was added since the system last ran
+    pub fn added<T: ComponentType>(mut self) -> Self {
+        self.query.added.insert(component::type_id::<T>());
+        self.with::<T>()
+    }
+    //only matches entities whose T was added or updated since the system last ran
+    pub fn changed<T: ComponentType>(mut self) -> Self {
+        self.query.changed.insert(component::type_id::<T>());
+        self.with::<T>()
+    }
     pub fn build(self) -> Query {
         self.query
     }
@@ -41,15 +148,37 @@ impl Default for QueryBuilder {
 pub struct QueryResult {
     entities: Vec<ComponentGroup>,
     resource_writer: resource_writer::ResourceWriter,
+    commands: command_buffer::CommandBuffer,
+    allocator: Arc<Mutex<entity_id::EntityAllocator>>,
+    rng: Option<StdRng>,
+    //what the query may write, None if unknown or it may write anything
+    writes: Option<HashSet<ComponentTypeId>>,
 }
 
 impl QueryResult {
-    pub(crate) fn dissolve(self) -> (Vec<Change>, resource_writer::ResourceWriter) {
+    //the changes to fetched entities, unfetched removals, the components of each spawned
+    //entity, the commands and the resource writes
+    pub(crate) fn dissolve(
+        self,
+    ) -> (
+        Vec<Change>,
+        Vec<component::ComponentInstanceId>,
+        Vec<Vec<UntypedComponent>>,
+        command_buffer::CommandBuffer,
+        resource_writer::ResourceWriter,
+    ) {
+        let (spawned, entities): (Vec<_>, Vec<_>) = self.entities.into_iter().partition(|x| x.new);
         (
-            self.entities
-                .into_iter()
-                .flat_map(|x| x.get_changes())
+            entities.iter().flat_map(|x| x.get_changes()).collect(),
+            entities
+                .iter()
+                .flat_map(|x| x.unfetched_removals())
+                .collect(),
+            spawned
+                .iter()
+                .map(|x| x.get_changes().into_iter().map(|x| x.0).collect())
                 .collect(),
+            self.commands,
             self.resource_writer,
         )
     }
@@ -62,32 +191,128 @@ impl QueryResult {
     ) {
         self.resource_writer.write_resource(closure);
     }
+    pub fn insert_resource<R: resource::Resource + 'static>(&mut self, resource: R) {
+        self.resource_writer.insert_resource(resource);
+    }
+    pub fn remove_resource<R: resource::Resource + 'static>(&mut self) {
+        self.resource_writer.remove_resource::<R>();
+    }
+    pub fn take_resource<R: resource::Resource + 'static>(
+        &mut self,
+        closure: impl FnOnce(R) + 'static + Send,
+    ) {
+        self.resource_writer.take_resource(closure);
+    }
+    //sent once the stage finishes, E has to be registered with WorldBuilder::with_event
+    pub fn send_event<E: Send + Sync + 'static>(&mut self, event: E) {
+        self.resource_writer
+            .write_resource(move |events: &mut events::Events<E>| events.send(event));
+    }
     pub fn add_entity(&mut self) -> entity_builder::EntityBuilder {
         entity_builder::EntityBuilder::new(self)
     }
+    //entity commands applied after the stage, for entities outside of the query. the query
+    //doesn't declare these writes, so stages don't check them for conflicts
+    pub fn commands(&mut self) -> &mut command_buffer::CommandBuffer {
+        &mut self.commands
+    }
+    pub fn despawn(&mut self, id: entity_id::EntityId) {
+        self.commands.despawn(id);
+    }
+    pub fn despawn_recursive(&mut self, id: entity_id::EntityId) {
+        self.commands.despawn_recursive(id);
+    }
+    pub fn insert_component<T: ComponentType>(&mut self, id: entity_id::EntityId, component: T) {
+        self.commands.insert(id, component);
+    }
+    pub fn remove_component<T: ComponentType>(&mut self, id: entity_id::EntityId) {
+        self.commands.remove::<T>(id);
+    }
+    //the system's random stream, reproducible for worlds built with a seed
+    pub fn rng(&mut self) -> &mut StdRng {
+        self.rng.get_or_insert_with(StdRng::from_entropy)
+    }
+    pub(crate) fn set_rng(&mut self, rng: StdRng) {
+        self.rng = Some(rng);
+    }
+    pub(crate) fn writes(&self) -> Option<&HashSet<ComponentTypeId>> {
+        self.writes.as_ref()
+    }
+    //calls f with the typed components of every entity that has D's required components
+    pub fn for_each<D: typed_query::QueryData>(
+        &mut self,
+        f: impl FnMut(entity_id::EntityId, D::Item<'_>),
+    ) {
+        typed_query::Query::<D>::new().for_each(self, f);
+    }
+    pub(crate) fn sort_by_id(&mut self) {
+        self.entities.sort_by_key(|x| x.id.id());
+    }
 }
 impl entity_builder::SpawnLocation for QueryResult {
-    fn spawn(&mut self, components: Vec<component::UntypedComponent>) {
-        self.entities.push(ComponentGroup {
-            id: entity_id::EntityId::new(),
-            components: components.into_iter().map(|x| (x.get_type(), x)).collect(),
-            new: true,
-        });
+    fn allocate_id(&mut self) -> Option<entity_id::EntityId> {
+        self.allocator.lock().allocate()
+    }
+    fn spawn(
+        &mut self,
+        components: Vec<component::UntypedComponent>,
+    ) -> Result<(), world::WorldError> {
+        if let Some(id) = components.first().map(|x| x.entity_id()) {
+            self.entities.push(ComponentGroup {
+                id,
+                components: components.into_iter().map(|x| (x.get_type(), x)).collect(),
+                new: true,
+                added: HashSet::new(),
+                removed: HashMap::new(),
+                unfetched_removed: HashSet::new(),
+                ticks: HashMap::new(),
+                last_run: 0,
+                system: 0,
+                read_only: Arc::new(HashSet::new()),
+            });
+        }
+        //validated when the stage applies the result, a rejection undoes the whole spawn
+        Ok(())
     }
 }
 
 pub struct QueryResultBuilder {
     query_result: QueryResult,
+    last_run: u64,
+    system: u64,
+    read_only: Arc<HashSet<ComponentTypeId>>,
 }
 impl QueryResultBuilder {
-    pub fn new() -> Self {
+    //entities spawned into the result get their ids from the allocator
+    pub fn new(allocator: Arc<Mutex<entity_id::EntityAllocator>>) -> Self {
         QueryResultBuilder {
             query_result: QueryResult {
                 entities: Vec::new(),
                 resource_writer: resource_writer::ResourceWriter::new(),
+                commands: command_buffer::CommandBuffer::new(),
+                allocator,
+                rng: None,
+                writes: None,
             },
+            last_run: 0,
+            system: 0,
+            read_only: Arc::new(HashSet::new()),
         }
     }
+    //the system's writes to components the query only reads are dropped, typed queries may
+    //only write what it writes
+    pub(crate) fn access(mut self, query: &Query) -> Self {
+        self.read_only = Arc::new(query.reads.clone());
+        self.query_result.writes = (!query.fetch_all).then(|| query.writes.clone());
+        self
+    }
+    //the change tick the system last ran at and its id, for ComponentGroup::is_added and
+    //is_changed
+    pub(crate) fn since(mut self, last_run: u64, system: u64) -> Self {
+        self.last_run = last_run;
+        self.system = system;
+        self
+    }
     pub fn with_entity(
         &mut self,
         components: impl IntoIterator<Item = UntypedComponent>,
@@ -98,21 +323,37 @@ impl QueryResultBuilder {
             .push(ComponentGroup::new(id, components));
         self
     }
+    pub(crate) fn with_tracked_entity(
+        &mut self,
+        components: impl IntoIterator<Item = UntypedComponent>,
+        id: entity_id::EntityId,
+        ticks: HashMap<ComponentTypeId, component::ComponentTicks>,
+    ) -> &mut Self {
+        let mut group = ComponentGroup::new(id, components);
+        group.ticks = ticks;
+        group.last_run = self.last_run;
+        group.system = self.system;
+        group.read_only = self.read_only.clone();
+        self.query_result.entities.push(group);
+        self
+    }
     pub fn build(self) -> QueryResult {
         self.query_result
     }
 }
 
-impl Default for QueryResultBuilder {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 pub struct ComponentGroup {
     id: entity_id::EntityId,
     components: HashMap<ComponentTypeId, UntypedComponent>,
     new: bool,
+    added: HashSet<ComponentTypeId>,
+    removed: HashMap<ComponentTypeId, UntypedComponent>,
+    //removals of types the query didn't fetch, the world looks them up when applying them
+    unfetched_removed: HashSet<ComponentTypeId>,
+    ticks: HashMap<ComponentTypeId, component::ComponentTicks>,
+    last_run: u64,
+    system: u64,
+    read_only: Arc<HashSet<ComponentTypeId>>,
 }
 
 pub struct TypedComponentWriteback<'a, T: ComponentType> {
@@ -144,6 +385,7 @@ impl<'a, T: ComponentType> Drop for TypedComponentWriteback<'a, T> {
 }
 
 impl ComponentGroup {
+    //None if the entity doesn't have T or the query didn't fetch it
     pub fn get<T: ComponentType>(&mut self) -> Option<TypedComponentWriteback<T>> {
         self.components
             .get(&component::type_id::<T>())
@@ -156,6 +398,12 @@ impl ComponentGroup {
     pub fn get_id(&self) -> entity_id::EntityId {
         self.id
     }
+    pub(crate) fn component(&self, tp: ComponentTypeId) -> Option<&UntypedComponent> {
+        self.components.get(&tp)
+    }
+    pub(crate) fn write_back(&mut self, component: UntypedComponent) {
+        self.components.insert(component.get_type(), component);
+    }
     pub fn new(
         id: entity_id::EntityId,
         components: impl IntoIterator<Item = UntypedComponent>,
@@ -167,29 +415,144 @@ impl ComponentGroup {
                 .collect(),
             id,
             new: false,
+            added: HashSet::new(),
+            removed: HashMap::new(),
+            unfetched_removed: HashSet::new(),
+            ticks: HashMap::new(),
+            last_run: 0,
+            system: 0,
+            read_only: Arc::new(HashSet::new()),
         }
     }
+    //whether T was added to the entity since the system last ran, by anything but the
+    //system itself
+    pub fn is_added<T: ComponentType>(&self) -> bool {
+        self.ticks
+            .get(&component::type_id::<T>())
+            .is_some_and(|x| x.is_added_by_other_since(self.last_run, self.system))
+    }
+    //whether T was added or updated since the system last ran, by anything but the system
+    //itself
+    pub fn is_changed<T: ComponentType>(&self) -> bool {
+        self.ticks
+            .get(&component::type_id::<T>())
+            .is_some_and(|x| x.is_changed_by_other_since(self.last_run, self.system))
+    }
+    pub fn get_ticks<T: ComponentType>(&self) -> Option<component::ComponentTicks> {
+        self.ticks.get(&component::type_id::<T>()).copied()
+    }
+    //added to the entity, or updated if it already has one, once the system finishes. this
+    //works for types the query didn't fetch too, the world checks which it is. such writes
+    //aren't declared by the query, so stages don't check them for conflicts
+    pub fn insert<T: ComponentType>(&mut self, component: T) {
+        let tp = component::type_id::<T>();
+        self.unfetched_removed.remove(&tp);
+        if !self.components.contains_key(&tp) && self.removed.remove(&tp).is_none() {
+            self.added.insert(tp);
+        }
+        self.components.insert(tp, component.into_untyped(self.id));
+    }
+    //removed from the entity once the system finishes. the removed value is only returned
+    //if the query fetched T, but unfetched components are removed all the same
+    pub fn remove<T: ComponentType>(&mut self) -> Option<T> {
+        let tp = component::type_id::<T>();
+        let component = match self.components.remove(&tp) {
+            Some(component) => component,
+            None => {
+                if !self.new && !self.removed.contains_key(&tp) {
+                    self.unfetched_removed.insert(tp);
+                }
+                return None;
+            }
+        };
+        let value = component.get_unchecked::<T>().clone();
+        if !self.new {
+            if !self.added.remove(&tp) {
+                self.removed.insert(tp, component);
+            } else {
+                //inserted by this system, but the entity may have had an unfetched one
+                self.unfetched_removed.insert(tp);
+            }
+        }
+        Some(value)
+    }
+    pub(crate) fn unfetched_removals(&self) -> Vec<component::ComponentInstanceId> {
+        //sorted for the same reason as get_changes
+        let mut removed = self.unfetched_removed.iter().copied().collect::<Vec<_>>();
+        removed.sort();
+        removed
+            .into_iter()
+            .map(|tp| component::ComponentInstanceId::new_explicit(self.id, tp))
+            .collect()
+    }
+    //systems running alongside the system may read what it only declared as read, so writes
+    //to those components are dropped
+    fn is_writable(&self, tp: ComponentTypeId) -> bool {
+        let writable = !self.read_only.contains(&tp);
+        debug_assert!(
+            writable,
+            "system wrote {:?}, which its query only reads",
+            tp
+        );
+        writable
+    }
     pub fn get_changes(&self) -> Vec<Change> {
-        self.components
-            .iter()
-            .filter(|(_, component)| component.is_unqiue())
-            .map(|(_, component)| {
-                Change(
-                    component.clone(),
-                    if self.new {
-                        ChangeType::AddComponent
-                    } else {
-                        ChangeType::UpdateComponent
-                    },
-                )
-            })
+        //sorted so changes are applied in the same order every run
+        let mut removed = self.removed.iter().collect::<Vec<_>>();
+        removed.sort_by_key(|(tp, _)| **tp);
+        let mut components = self.components.iter().collect::<Vec<_>>();
+        components.sort_by_key(|(tp, _)| **tp);
+        removed
+            .into_iter()
+            .filter(|(tp, _)| self.is_writable(**tp))
+            .map(|(_, component)| Change::new(component.clone(), ChangeType::RemoveComponent))
+            .chain(
+                components
+                    .into_iter()
+                    .filter(|(tp, component)| component.is_unqiue() && self.is_writable(**tp))
+                    .map(|(tp, component)| {
+                        Change::new(
+                            component.clone(),
+                            if self.new || self.added.contains(tp) {
+                                ChangeType::AddComponent
+                            } else {
+                                ChangeType::UpdateComponent
+                            },
+                        )
+                    }),
+            )
             .collect()
     }
 }
 
-pub struct Change(pub UntypedComponent, pub ChangeType);
+//the component, what happens to it, and the value it replaces or removes. the world fills
+//in the previous value from storage before hooks see the change
+pub struct Change(
+    pub UntypedComponent,
+    pub ChangeType,
+    pub Option<UntypedComponent>,
+);
+
+impl Change {
+    pub fn new(component: UntypedComponent, change_type: ChangeType) -> Self {
+        Change(component, change_type, None)
+    }
+    pub fn get<T: ComponentType>(&self) -> Option<&T> {
+        self.0.get::<T>()
+    }
+    pub fn previous<T: ComponentType>(&self) -> Option<&T> {
+        self.2.as_ref().and_then(|x| x.get::<T>())
+    }
+    //the previous and new value of an updated T
+    pub fn update<T: ComponentType>(&self) -> Option<(&T, &T)> {
+        match self.1 {
+            ChangeType::UpdateComponent => Some((self.previous::<T>()?, self.get::<T>()?)),
+            _ => None,
+        }
+    }
+}
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
 pub enum ChangeType {
     RemoveComponent,
     UnloadComponent,
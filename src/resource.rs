@@ -19,6 +19,9 @@ impl UntypedResource {
     pub(crate) fn get_as_mut<T: Resource + 'static>(&mut self) -> &mut T {
         self.data.downcast_mut::<T>().unwrap()
     }
+    pub(crate) fn into_inner<T: Resource + 'static>(self) -> T {
+        *self.data.downcast::<T>().unwrap()
+    }
 }
 
 pub(crate) fn get_resource_id<T: Resource>() -> u64 {
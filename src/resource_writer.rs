@@ -1,7 +1,10 @@
 use crate::resource;
 use crate::world;
-pub type WorldReferenceWriteClosure = Box<dyn FnOnce(&mut world::World) + Send>;
+pub type WorldReferenceWriteClosure =
+    Box<dyn FnOnce(&mut world::World) -> Result<(), world::WorldError> + Send>;
 
+//resource writes deferred until the world applies them. writes to a missing resource
+//are reported by the world instead of panicking
 pub struct ResourceWriter {
     world_reference_closure: Vec<WorldReferenceWriteClosure>,
 }
@@ -21,7 +24,31 @@ impl ResourceWriter {
     ) {
         self.world_reference_closure
             .push(Box::new(move |world: &mut world::World| {
-                world.write_resource::<R, ReturnType>(closure).unwrap();
+                world.write_resource::<R, ReturnType>(closure).map(|_| ())
+            }));
+    }
+    //replaces the resource if there already is one
+    pub fn insert_resource<R: resource::Resource + 'static>(&mut self, resource: R) {
+        self.world_reference_closure
+            .push(Box::new(move |world: &mut world::World| {
+                world.insert_resource(resource);
+                Ok(())
+            }));
+    }
+    pub fn remove_resource<R: resource::Resource + 'static>(&mut self) {
+        self.world_reference_closure
+            .push(Box::new(|world: &mut world::World| {
+                world.remove_resource::<R>()
+            }));
+    }
+    //removes the resource and hands it to the closure
+    pub fn take_resource<R: resource::Resource + 'static>(
+        &mut self,
+        closure: impl FnOnce(R) + 'static + Send,
+    ) {
+        self.world_reference_closure
+            .push(Box::new(move |world: &mut world::World| {
+                world.take_resource::<R>().map(closure)
             }));
     }
 }
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+use crate::{clock, schedule, stage, world};
+
+//something the runner steps once per tick
+pub trait Runnable {
+    fn run(&self, world: &mut world::World);
+}
+
+impl Runnable for schedule::Schedule {
+    fn run(&self, world: &mut world::World) {
+        world.run_schedule(self);
+    }
+}
+
+//a stage on its own is a whole tick
+impl Runnable for stage::Stage {
+    fn run(&self, world: &mut world::World) {
+        world.execute_stage(self);
+        world.update_events();
+    }
+}
+
+//advances the world's clock and runs one tick, unless the clock is paused
+pub fn step(world: &mut world::World, runnable: &impl Runnable) -> Result<bool, world::WorldError> {
+    let running = world.write_resource(|x: &mut clock::Clock| {
+        if !x.is_paused() {
+            x.advance();
+        }
+        !x.is_paused()
+    })?;
+    if running {
+        runnable.run(world);
+    }
+    Ok(running)
+}
+
+//headless mode, runs n ticks as fast as possible. paused ticks are skipped
+pub fn run_ticks(
+    world: &mut world::World,
+    runnable: &impl Runnable,
+    n: u64,
+) -> Result<u64, world::WorldError> {
+    let mut ran = 0;
+    for _ in 0..n {
+        if step(world, runnable)? {
+            ran += 1;
+        }
+    }
+    Ok(ran)
+}
+
+//steps at the world clock's fixed rate, catching up on steps missed while the game was busy
+pub struct FixedTimestepRunner {
+    //at most this many steps per update, time beyond that is dropped
+    max_catch_up: u32,
+    accumulator: Duration,
+    last_update: Option<Instant>,
+}
+
+impl FixedTimestepRunner {
+    pub fn new() -> Self {
+        FixedTimestepRunner {
+            max_catch_up: 5,
+            accumulator: Duration::ZERO,
+            last_update: None,
+        }
+    }
+    pub fn with_max_catch_up(mut self, max_catch_up: u32) -> Self {
+        self.max_catch_up = max_catch_up.max(1);
+        self
+    }
+    //runs the steps that fit into the given real time, returning how many ran
+    pub fn advance(
+        &mut self,
+        world: &mut world::World,
+        runnable: &impl Runnable,
+        elapsed: Duration,
+    ) -> Result<u32, world::WorldError> {
+        let timestep = world.read_resource(|x: &clock::Clock| x.timestep())?;
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= timestep && !timestep.is_zero() {
+            if steps == self.max_catch_up {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= timestep;
+            if step(world, runnable)? {
+                steps += 1;
+            }
+        }
+        Ok(steps)
+    }
+    //advances by the real time since the previous update
+    pub fn update(
+        &mut self,
+        world: &mut world::World,
+        runnable: &impl Runnable,
+    ) -> Result<u32, world::WorldError> {
+        let now = Instant::now();
+        let elapsed = self.last_update.map_or(Duration::ZERO, |x| now - x);
+        self.last_update = Some(now);
+        self.advance(world, runnable, elapsed)
+    }
+    //steps in real time until stop returns true, sleeping between steps
+    pub fn run(
+        &mut self,
+        world: &mut world::World,
+        runnable: &impl Runnable,
+        mut stop: impl FnMut(&world::World) -> bool,
+    ) -> Result<(), world::WorldError> {
+        let timestep = world.read_resource(|x: &clock::Clock| x.timestep())?;
+        self.last_update = Some(Instant::now());
+        while !stop(world) {
+            self.update(world, runnable)?;
+            std::thread::sleep(timestep.saturating_sub(self.accumulator));
+        }
+        Ok(())
+    }
+}
+
+impl Default for FixedTimestepRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
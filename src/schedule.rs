@@ -0,0 +1,270 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{resource, stage, system, world};
+
+pub const STARTUP: &str = "startup";
+pub const PRE_UPDATE: &str = "pre_update";
+pub const UPDATE: &str = "update";
+pub const POST_UPDATE: &str = "post_update";
+
+//decides whether a system or stage runs this time it is reached
+pub struct RunCriteria {
+    check: Box<dyn Fn(&world::World) -> bool + Send + Sync>,
+}
+
+impl RunCriteria {
+    pub fn new(check: impl Fn(&world::World) -> bool + Send + Sync + 'static) -> Self {
+        RunCriteria {
+            check: Box::new(check),
+        }
+    }
+    //runs the first time it is reached, then every n times after that
+    pub fn every(n: u64) -> Self {
+        let count = AtomicU64::new(0);
+        Self::new(move |_| {
+            count
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(n.max(1))
+        })
+    }
+    //runs while the resource exists and the flag returns true
+    pub fn resource<R: resource::Resource + 'static>(
+        flag: impl Fn(&R) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(move |world| world.read_resource(|x: &R| flag(x)).unwrap_or(false))
+    }
+    pub(crate) fn should_run(&self, world: &world::World) -> bool {
+        (self.check)(world)
+    }
+}
+
+//a system with the labels, ordering and run criteria it was added to a stage with
+pub struct SystemDescriptor {
+    pub(crate) system: Box<dyn system::System>,
+    pub(crate) labels: Vec<String>,
+    pub(crate) before: Vec<String>,
+    pub(crate) after: Vec<String>,
+    pub(crate) run_criteria: Option<RunCriteria>,
+}
+
+impl SystemDescriptor {
+    pub fn label(mut self, label: &str) -> Self {
+        self.labels.push(label.to_string());
+        self
+    }
+    //runs before every system in the same stage with the label
+    pub fn before(mut self, label: &str) -> Self {
+        self.before.push(label.to_string());
+        self
+    }
+    //runs after every system in the same stage with the label
+    pub fn after(mut self, label: &str) -> Self {
+        self.after.push(label.to_string());
+        self
+    }
+    pub fn run_if(mut self, criteria: RunCriteria) -> Self {
+        self.run_criteria = Some(criteria);
+        self
+    }
+}
+
+pub trait IntoSystemDescriptor {
+    fn into_descriptor(self) -> SystemDescriptor;
+    fn label(self, label: &str) -> SystemDescriptor
+    where
+        Self: Sized,
+    {
+        self.into_descriptor().label(label)
+    }
+    fn before(self, label: &str) -> SystemDescriptor
+    where
+        Self: Sized,
+    {
+        self.into_descriptor().before(label)
+    }
+    fn after(self, label: &str) -> SystemDescriptor
+    where
+        Self: Sized,
+    {
+        self.into_descriptor().after(label)
+    }
+    fn run_if(self, criteria: RunCriteria) -> SystemDescriptor
+    where
+        Self: Sized,
+    {
+        self.into_descriptor().run_if(criteria)
+    }
+}
+
+impl<T: system::System + 'static> IntoSystemDescriptor for T {
+    fn into_descriptor(self) -> SystemDescriptor {
+        SystemDescriptor {
+            system: Box::new(self),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            run_criteria: None,
+        }
+    }
+}
+
+impl IntoSystemDescriptor for SystemDescriptor {
+    fn into_descriptor(self) -> SystemDescriptor {
+        self
+    }
+}
+
+struct ScheduledStage {
+    name: String,
+    stage: stage::Stage,
+    run_criteria: Option<RunCriteria>,
+}
+
+//named stages run in order by World::run_schedule, the startup stage only on the first run
+pub struct Schedule {
+    startup: Option<stage::Stage>,
+    stages: Vec<ScheduledStage>,
+    started: AtomicBool,
+}
+
+impl Schedule {
+    pub fn get_stage(&self, name: &str) -> Option<&stage::Stage> {
+        self.stages
+            .iter()
+            .find(|x| x.name == name)
+            .map(|x| &x.stage)
+    }
+    pub fn stage_names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|x| x.name.as_str())
+    }
+    //the startup stage if it hasn't run yet, then every stage whose run criteria pass
+    pub(crate) fn run(&self, world: &mut world::World) {
+        if !self.started.swap(true, Ordering::Relaxed) {
+            if let Some(startup) = &self.startup {
+                world.execute_stage(startup);
+            }
+        }
+        for stage in &self.stages {
+            if stage
+                .run_criteria
+                .as_ref()
+                .is_none_or(|x| x.should_run(world))
+            {
+                world.execute_stage(&stage.stage);
+            }
+        }
+    }
+}
+
+struct StageEntry {
+    name: String,
+    stage: stage::StageBuilder,
+    run_criteria: Option<RunCriteria>,
+}
+
+pub struct ScheduleBuilder {
+    startup: stage::StageBuilder,
+    stages: Vec<StageEntry>,
+}
+
+impl ScheduleBuilder {
+    //starts with the startup, pre_update, update and post_update stages
+    pub fn new() -> Self {
+        ScheduleBuilder {
+            startup: stage::StageBuilder::new(),
+            stages: Vec::new(),
+        }
+        .with_stage(PRE_UPDATE)
+        .with_stage(UPDATE)
+        .with_stage(POST_UPDATE)
+    }
+    //a schedule without any stages besides startup
+    pub fn empty() -> Self {
+        ScheduleBuilder {
+            startup: stage::StageBuilder::new(),
+            stages: Vec::new(),
+        }
+    }
+    fn position(&self, name: &str) -> usize {
+        self.stages
+            .iter()
+            .position(|x| x.name == name)
+            .unwrap_or_else(|| panic!("stage {} not found", name))
+    }
+    fn insert_stage(mut self, index: usize, name: &str) -> Self {
+        assert!(
+            name != STARTUP && self.stages.iter().all(|x| x.name != name),
+            "stage {} already exists",
+            name
+        );
+        self.stages.insert(
+            index,
+            StageEntry {
+                name: name.to_string(),
+                stage: stage::StageBuilder::new(),
+                run_criteria: None,
+            },
+        );
+        self
+    }
+    pub fn with_stage(self, name: &str) -> Self {
+        let index = self.stages.len();
+        self.insert_stage(index, name)
+    }
+    pub fn with_stage_before(self, before: &str, name: &str) -> Self {
+        let index = self.position(before);
+        self.insert_stage(index, name)
+    }
+    pub fn with_stage_after(self, after: &str, name: &str) -> Self {
+        let index = self.position(after) + 1;
+        self.insert_stage(index, name)
+    }
+    pub fn with_stage_run_criteria(mut self, name: &str, criteria: RunCriteria) -> Self {
+        let index = self.position(name);
+        self.stages[index].run_criteria = Some(criteria);
+        self
+    }
+    //adds the system to the named stage, or to the startup stage
+    pub fn with_system(mut self, stage: &str, system: impl IntoSystemDescriptor) -> Self {
+        if stage == STARTUP {
+            self.startup = self.startup.with_system(system);
+        } else {
+            let index = self.position(stage);
+            let entry = &mut self.stages[index];
+            entry.stage = std::mem::take(&mut entry.stage).with_system(system);
+        }
+        self
+    }
+    pub fn with_startup_system(self, system: impl IntoSystemDescriptor) -> Self {
+        self.with_system(STARTUP, system)
+    }
+    //fails if the systems of a stage have cyclic before/after dependencies
+    pub fn try_build(self) -> Result<Schedule, world::WorldError> {
+        let startup = self.startup.try_build_ordered()?;
+        let stages = self
+            .stages
+            .into_iter()
+            .map(|x| {
+                Ok(ScheduledStage {
+                    name: x.name,
+                    stage: x.stage.try_build_ordered()?,
+                    run_criteria: x.run_criteria,
+                })
+            })
+            .collect::<Result<Vec<_>, world::WorldError>>()?;
+        Ok(Schedule {
+            startup: (!startup.is_empty()).then_some(startup),
+            stages,
+            started: AtomicBool::new(false),
+        })
+    }
+    pub fn build(self) -> Schedule {
+        self.try_build().expect("cyclic system ordering")
+    }
+}
+
+impl Default for ScheduleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,176 @@
+use hashbrown::HashSet;
+
+use crate::{component, component_registry, entity_id, hook, world};
+
+#[derive(Debug)]
+pub enum SledStoreError {
+    SledError(sled::Error),
+    WorldError(world::WorldError),
+    InvalidKey,
+}
+
+//stores components in a sled tree as CBOR, keyed by entity id followed by component type id.
+//components must be registered with the world to be stored. the world's entity allocator is
+//stored on every unload, restore it from SledStore::allocator with WorldBuilder::with_allocator
+//so a restarted world doesn't reissue the ids of stored entities. loading an entity deletes its
+//stored copy, it is written again on the next unload
+#[derive(Clone)]
+pub struct SledStore {
+    tree: sled::Tree,
+    meta: sled::Tree,
+}
+
+const ALLOCATOR_KEY: &[u8] = b"allocator";
+
+fn key(id: component::ComponentInstanceId) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&id.entity_id().id().to_be_bytes());
+    key[8..].copy_from_slice(&id.component_type_id().num().to_be_bytes());
+    key
+}
+
+fn type_from_key(key: &[u8]) -> Result<component::ComponentTypeId, SledStoreError> {
+    key.get(8..16)
+        .and_then(|x| x.try_into().ok())
+        .map(|x| component::ComponentTypeId::new_with_number(u64::from_be_bytes(x)))
+        .ok_or(SledStoreError::InvalidKey)
+}
+
+impl SledStore {
+    pub fn new(db: &sled::Db) -> Result<Self, SledStoreError> {
+        Ok(SledStore {
+            tree: db
+                .open_tree("components")
+                .map_err(SledStoreError::SledError)?,
+            meta: db.open_tree("meta").map_err(SledStoreError::SledError)?,
+        })
+    }
+    pub fn open(path: &std::path::Path) -> Result<Self, SledStoreError> {
+        Self::new(&sled::open(path).map_err(SledStoreError::SledError)?)
+    }
+    pub fn store(
+        &self,
+        components: &[component::UntypedComponent],
+        registry: &component_registry::ComponentRegistry,
+    ) -> Result<(), SledStoreError> {
+        let mut batch = sled::Batch::default();
+        //drop what was stored before so removed components don't come back on load
+        let mut cleared = HashSet::new();
+        for c in components {
+            if cleared.insert(c.entity_id()) {
+                for k in self
+                    .tree
+                    .scan_prefix(c.entity_id().id().to_be_bytes())
+                    .keys()
+                {
+                    batch.remove(k.map_err(SledStoreError::SledError)?);
+                }
+            }
+        }
+        for c in components {
+            batch.insert(
+                &key(c.id()),
+                registry.to_cbor(c).map_err(SledStoreError::WorldError)?,
+            );
+        }
+        self.tree
+            .apply_batch(batch)
+            .map_err(SledStoreError::SledError)?;
+        self.tree.flush().map_err(SledStoreError::SledError)?;
+        Ok(())
+    }
+    pub fn store_allocator(
+        &self,
+        allocator: &entity_id::EntityAllocator,
+    ) -> Result<(), SledStoreError> {
+        let data = serde_cbor::to_vec(allocator).map_err(|e| {
+            SledStoreError::WorldError(world::WorldError::SerializationError(e.to_string()))
+        })?;
+        self.meta
+            .insert(ALLOCATOR_KEY, data)
+            .map_err(SledStoreError::SledError)?;
+        self.meta.flush().map_err(SledStoreError::SledError)?;
+        Ok(())
+    }
+    //the allocator saved by the last unload, None if nothing was unloaded yet
+    pub fn allocator(&self) -> Result<Option<entity_id::EntityAllocator>, SledStoreError> {
+        self.meta
+            .get(ALLOCATOR_KEY)
+            .map_err(SledStoreError::SledError)?
+            .map(|x| {
+                serde_cbor::from_slice(&x).map_err(|e| {
+                    SledStoreError::WorldError(world::WorldError::SerializationError(e.to_string()))
+                })
+            })
+            .transpose()
+    }
+    //drops everything stored for the entities
+    pub fn delete(&self, ids: &[entity_id::EntityId]) -> Result<(), SledStoreError> {
+        let mut batch = sled::Batch::default();
+        for id in ids {
+            for k in self.tree.scan_prefix(id.id().to_be_bytes()).keys() {
+                batch.remove(k.map_err(SledStoreError::SledError)?);
+            }
+        }
+        self.tree
+            .apply_batch(batch)
+            .map_err(SledStoreError::SledError)?;
+        self.tree.flush().map_err(SledStoreError::SledError)?;
+        Ok(())
+    }
+    pub fn fetch(
+        &self,
+        id: entity_id::EntityId,
+        registry: &component_registry::ComponentRegistry,
+    ) -> Result<Vec<component::UntypedComponent>, SledStoreError> {
+        self.tree
+            .scan_prefix(id.id().to_be_bytes())
+            .map(|x| {
+                let (k, v) = x.map_err(SledStoreError::SledError)?;
+                registry
+                    .from_cbor(type_from_key(&k)?, &v, id)
+                    .map_err(SledStoreError::WorldError)
+            })
+            .collect()
+    }
+}
+
+fn to_world_error(e: SledStoreError) -> world::WorldError {
+    match e {
+        SledStoreError::WorldError(e) => e,
+        e => world::WorldError::StoreError(format!("{:?}", e)),
+    }
+}
+
+impl hook::Unloader for SledStore {
+    fn hook(
+        &self,
+        components: Vec<component::UntypedComponent>,
+        world: &world::World,
+    ) -> Result<(), world::WorldError> {
+        self.store(&components, world.registry())
+            .and_then(|_| self.store_allocator(&world.allocator()))
+            .map_err(to_world_error)
+    }
+}
+
+impl hook::Loader for SledStore {
+    fn load(
+        &self,
+        ids: Vec<entity_id::EntityId>,
+        world: &world::World,
+    ) -> Result<Vec<component::UntypedComponent>, world::WorldError> {
+        let mut components = Vec::new();
+        for id in ids {
+            components.extend(self.fetch(id, world.registry()).map_err(to_world_error)?);
+        }
+        Ok(components)
+    }
+    fn forget(
+        &self,
+        ids: Vec<entity_id::EntityId>,
+        _world: &world::World,
+    ) -> Result<(), world::WorldError> {
+        self.delete(&ids).map_err(to_world_error)
+    }
+}
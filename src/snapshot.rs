@@ -0,0 +1,121 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{component, component_registry, entity_id, resource, world};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnapshotFormat {
+    Cbor,
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Snapshot<T> {
+    pub entities: Vec<SnapshotEntity<T>>,
+    pub resources: Vec<(u64, T)>,
+    #[serde(default)]
+    pub allocator: Option<entity_id::EntityAllocator>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SnapshotEntity<T> {
+    pub id: entity_id::EntityId,
+    pub components: Vec<(component::ComponentTypeId, T)>,
+}
+
+//how a single component or resource is stored inside a snapshot of a given format
+pub(crate) trait SnapshotData: Serialize + DeserializeOwned {
+    fn from_component(
+        registry: &component_registry::ComponentRegistry,
+        component: &component::UntypedComponent,
+    ) -> Result<Self, world::WorldError>;
+    fn into_component(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: component::ComponentTypeId,
+        entity: entity_id::EntityId,
+    ) -> Result<component::UntypedComponent, world::WorldError>;
+    fn from_resource(
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+        resource: &resource::UntypedResource,
+    ) -> Result<Self, world::WorldError>;
+    fn into_resource(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+    ) -> Result<resource::UntypedResource, world::WorldError>;
+}
+
+impl SnapshotData for serde_json::Value {
+    fn from_component(
+        registry: &component_registry::ComponentRegistry,
+        component: &component::UntypedComponent,
+    ) -> Result<Self, world::WorldError> {
+        registry.to_json(component)
+    }
+    fn into_component(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: component::ComponentTypeId,
+        entity: entity_id::EntityId,
+    ) -> Result<component::UntypedComponent, world::WorldError> {
+        registry.from_json(id, self, entity)
+    }
+    fn from_resource(
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+        resource: &resource::UntypedResource,
+    ) -> Result<Self, world::WorldError> {
+        registry.resource_to_json(id, resource)
+    }
+    fn into_resource(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+    ) -> Result<resource::UntypedResource, world::WorldError> {
+        registry.resource_from_json(id, self)
+    }
+}
+
+//cbor snapshots store each component as an embedded byte string
+fn expect_bytes(value: serde_cbor::Value) -> Result<Vec<u8>, world::WorldError> {
+    match value {
+        serde_cbor::Value::Bytes(bytes) => Ok(bytes),
+        _ => Err(world::WorldError::SerializationError(
+            "expected byte string in snapshot".to_string(),
+        )),
+    }
+}
+
+impl SnapshotData for serde_cbor::Value {
+    fn from_component(
+        registry: &component_registry::ComponentRegistry,
+        component: &component::UntypedComponent,
+    ) -> Result<Self, world::WorldError> {
+        registry.to_cbor(component).map(serde_cbor::Value::Bytes)
+    }
+    fn into_component(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: component::ComponentTypeId,
+        entity: entity_id::EntityId,
+    ) -> Result<component::UntypedComponent, world::WorldError> {
+        registry.from_cbor(id, &expect_bytes(self)?, entity)
+    }
+    fn from_resource(
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+        resource: &resource::UntypedResource,
+    ) -> Result<Self, world::WorldError> {
+        registry
+            .resource_to_cbor(id, resource)
+            .map(serde_cbor::Value::Bytes)
+    }
+    fn into_resource(
+        self,
+        registry: &component_registry::ComponentRegistry,
+        id: u64,
+    ) -> Result<resource::UntypedResource, world::WorldError> {
+        registry.resource_from_cbor(id, &expect_bytes(self)?)
+    }
+}
@@ -1,19 +1,58 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//system ids are unique across stages, so change ticks can tell which system made a change
+static NEXT_SYSTEM_ID: AtomicU64 = AtomicU64::new(1);
+
+use hashbrown::HashSet;
 use rayon::prelude::IntoParallelRefIterator;
 
-use crate::system;
+use crate::{
+    schedule::{self, IntoSystemDescriptor},
+    system, world,
+};
 
 pub struct Stage {
     systems: Vec<Box<dyn system::System>>,
+    run_criteria: Vec<Option<schedule::RunCriteria>>,
+    //world change tick of each system's last run, 0 if it never ran
+    last_runs: Vec<AtomicU64>,
+    ids: Vec<u64>,
+    //indexes of systems that can run in parallel. batches run in order, each seeing the
+    //changes of the ones before it
+    batches: Vec<Vec<usize>>,
 }
 
 impl Stage {
     pub fn iter(&self) -> rayon::slice::Iter<'_, Box<dyn system::System>> {
         self.systems.par_iter()
     }
+    pub fn batches(&self) -> &[Vec<usize>] {
+        &self.batches
+    }
+    pub fn is_empty(&self) -> bool {
+        self.systems.is_empty()
+    }
+    pub(crate) fn system(&self, index: usize) -> &dyn system::System {
+        self.systems[index].as_ref()
+    }
+    pub(crate) fn should_run(&self, index: usize, world: &world::World) -> bool {
+        self.run_criteria[index]
+            .as_ref()
+            .is_none_or(|x| x.should_run(world))
+    }
+    pub(crate) fn last_run(&self, system: usize) -> u64 {
+        self.last_runs[system].load(Ordering::Relaxed)
+    }
+    pub(crate) fn set_last_run(&self, system: usize, tick: u64) {
+        self.last_runs[system].store(tick, Ordering::Relaxed);
+    }
+    pub(crate) fn system_id(&self, system: usize) -> u64 {
+        self.ids[system]
+    }
 }
 
 pub struct StageBuilder {
-    systems: Vec<Box<dyn system::System>>,
+    systems: Vec<schedule::SystemDescriptor>,
 }
 
 impl StageBuilder {
@@ -22,14 +61,116 @@ impl StageBuilder {
             systems: Vec::new(),
         }
     }
-    pub fn with_system(mut self, system: impl system::System + 'static) -> Self {
-        self.systems.push(Box::new(system));
+    pub fn with_system(mut self, system: impl IntoSystemDescriptor) -> Self {
+        self.systems.push(system.into_descriptor());
         self
     }
+    //indexes of the systems each system has to run after, from its before/after labels
+    fn dependencies(&self) -> Vec<HashSet<usize>> {
+        let labeled = |label: &String| {
+            self.systems
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| x.labels.contains(label))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        let mut dependencies = vec![HashSet::new(); self.systems.len()];
+        for (i, system) in self.systems.iter().enumerate() {
+            for j in system.after.iter().flat_map(labeled) {
+                dependencies[i].insert(j);
+            }
+            for j in system.before.iter().flat_map(labeled) {
+                dependencies[j].insert(i);
+            }
+        }
+        dependencies
+    }
+    //systems run after their dependencies, and systems that write components another
+    //system reads or writes run after it, in the order they were added
+    pub(crate) fn try_build_ordered(self) -> Result<Stage, world::WorldError> {
+        let dependencies = self.dependencies();
+        let queries = self
+            .systems
+            .iter()
+            .map(|x| x.system.query())
+            .collect::<Vec<_>>();
+        let mut batch_of: Vec<Option<usize>> = vec![None; self.systems.len()];
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        while let Some(i) = (0..self.systems.len()).find(|i| {
+            batch_of[*i].is_none() && dependencies[*i].iter().all(|x| batch_of[*x].is_some())
+        }) {
+            //the batch after the last one holding a dependency or conflicting system
+            let first = (0..self.systems.len())
+                .filter(|x| dependencies[i].contains(x) || queries[*x].conflicts_with(&queries[i]))
+                .filter_map(|x| batch_of[x])
+                .max()
+                .map_or(0, |x| x + 1);
+            match batches.get_mut(first) {
+                Some(batch) => batch.push(i),
+                None => batches.push(vec![i]),
+            }
+            batch_of[i] = Some(first);
+        }
+        let cycle = (0..self.systems.len())
+            .filter(|x| batch_of[*x].is_none())
+            .collect::<Vec<_>>();
+        if !cycle.is_empty() {
+            return Err(world::WorldError::SystemOrderCycle(cycle));
+        }
+        let (systems, run_criteria) = self
+            .systems
+            .into_iter()
+            .map(|x| (x.system, x.run_criteria))
+            .unzip();
+        Ok(Stage {
+            last_runs: batch_of.iter().map(|_| AtomicU64::new(0)).collect(),
+            ids: batch_of
+                .iter()
+                .map(|_| NEXT_SYSTEM_ID.fetch_add(1, Ordering::Relaxed))
+                .collect(),
+            systems,
+            run_criteria,
+            batches,
+        })
+    }
     pub fn build(self) -> Stage {
-        Stage {
-            systems: self.systems,
+        self.try_build_ordered().expect("cyclic system ordering")
+    }
+    //fails with the first pair of unordered systems whose component access conflicts,
+    //or if the before/after dependencies are cyclic
+    pub fn try_build(self) -> Result<Stage, world::WorldError> {
+        let dependencies = self.dependencies();
+        //whether i has to run after j, directly or through other systems
+        let runs_after = |i: usize, j: usize| {
+            let mut stack = vec![i];
+            let mut seen = HashSet::new();
+            while let Some(x) = stack.pop() {
+                if dependencies[x].contains(&j) {
+                    return true;
+                }
+                stack.extend(dependencies[x].iter().filter(|x| seen.insert(**x)));
+            }
+            false
+        };
+        let queries = self
+            .systems
+            .iter()
+            .map(|x| x.system.query())
+            .collect::<Vec<_>>();
+        let stage = self.try_build_ordered()?;
+        for (i, first) in queries.iter().enumerate() {
+            for (j, second) in queries.iter().enumerate().skip(i + 1) {
+                if first.conflicts_with(second) && !runs_after(i, j) && !runs_after(j, i) {
+                    return Err(world::WorldError::SystemConflict(
+                        i,
+                        j,
+                        first.conflicts(second),
+                    ));
+                }
+            }
         }
+        Ok(stage)
     }
 }
 
@@ -0,0 +1,154 @@
+use hashbrown::{HashMap, HashSet};
+use rayon::prelude::*;
+
+use crate::{archetype_storage, component, entity_id, query};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageMode {
+    //every component is kept in one map, indexed by entity and by component type
+    Sparse,
+    //entities with the same set of components share an archetype with a column per type
+    Archetype,
+}
+
+pub(crate) type QueryVisitor<'a> =
+    dyn FnMut(entity_id::EntityId, &mut dyn Iterator<Item = &component::UntypedComponent>) + 'a;
+
+pub(crate) trait ComponentStorage: Send + Sync {
+    //inserts or replaces a component, returning the replaced one
+    fn insert(
+        &mut self,
+        component: component::UntypedComponent,
+    ) -> Option<component::UntypedComponent>;
+    //inserts or replaces several components, returning the replaced ones in the same order
+    fn insert_all(
+        &mut self,
+        components: Vec<component::UntypedComponent>,
+    ) -> Vec<Option<component::UntypedComponent>> {
+        components.into_iter().map(|x| self.insert(x)).collect()
+    }
+    fn remove(&mut self, id: component::ComponentInstanceId)
+        -> Option<component::UntypedComponent>;
+    fn get(&self, id: component::ComponentInstanceId) -> Option<&component::UntypedComponent>;
+    fn contains_entity(&self, id: entity_id::EntityId) -> bool;
+    fn entity_components(
+        &self,
+        id: entity_id::EntityId,
+    ) -> Option<Box<dyn Iterator<Item = &component::UntypedComponent> + '_>>;
+    fn entities(&self) -> Box<dyn Iterator<Item = entity_id::EntityId> + '_>;
+    fn number_of_entities(&self) -> usize;
+    //visits every entity that has all of the query's components and none of its excluded ones
+    fn query(&self, query: &query::Query, visit: &mut QueryVisitor);
+}
+
+pub(crate) fn new_storage(mode: StorageMode) -> Box<dyn ComponentStorage> {
+    match mode {
+        StorageMode::Sparse => Box::new(SparseStorage::new()),
+        StorageMode::Archetype => Box::new(archetype_storage::ArchetypeStorage::new()),
+    }
+}
+
+pub(crate) struct SparseStorage {
+    components: HashMap<component::ComponentInstanceId, component::UntypedComponent>,
+    entities: HashMap<entity_id::EntityId, HashSet<component::ComponentInstanceId>>,
+    components_types: HashMap<component::ComponentTypeId, HashSet<entity_id::EntityId>>,
+}
+
+impl SparseStorage {
+    pub(crate) fn new() -> Self {
+        SparseStorage {
+            components: HashMap::new(),
+            entities: HashMap::new(),
+            components_types: HashMap::new(),
+        }
+    }
+}
+
+impl ComponentStorage for SparseStorage {
+    fn insert(
+        &mut self,
+        component: component::UntypedComponent,
+    ) -> Option<component::UntypedComponent> {
+        let tid = component.get_type();
+        let eid = component.entity_id();
+        let cid = component.id();
+        self.entities.entry(eid).or_default().insert(cid);
+        self.components_types.entry(tid).or_default().insert(eid);
+        self.components.insert(cid, component)
+    }
+    fn remove(
+        &mut self,
+        id: component::ComponentInstanceId,
+    ) -> Option<component::UntypedComponent> {
+        let tid = id.component_type_id();
+        let eid = id.entity_id();
+        if let Some(set) = self.entities.get_mut(&eid) {
+            set.remove(&id);
+            if set.is_empty() {
+                self.entities.remove(&eid);
+            }
+        }
+        if let Some(set) = self.components_types.get_mut(&tid) {
+            set.remove(&eid);
+            if set.is_empty() {
+                self.components_types.remove(&tid);
+            }
+        }
+        self.components.remove(&id)
+    }
+    fn get(&self, id: component::ComponentInstanceId) -> Option<&component::UntypedComponent> {
+        self.components.get(&id)
+    }
+    fn contains_entity(&self, id: entity_id::EntityId) -> bool {
+        self.entities.contains_key(&id)
+    }
+    fn entity_components(
+        &self,
+        id: entity_id::EntityId,
+    ) -> Option<Box<dyn Iterator<Item = &component::UntypedComponent> + '_>> {
+        self.entities.get(&id).map(|set| {
+            Box::new(
+                set.iter()
+                    .map(|x| self.components.get(x).expect("ECS invarient broken")),
+            ) as Box<dyn Iterator<Item = _>>
+        })
+    }
+    fn entities(&self) -> Box<dyn Iterator<Item = entity_id::EntityId> + '_> {
+        Box::new(self.entities.keys().copied())
+    }
+    fn number_of_entities(&self) -> usize {
+        self.entities.len()
+    }
+    fn query(&self, query: &query::Query, visit: &mut QueryVisitor) {
+        let mut sets = Vec::new();
+        for tp in &query.components {
+            match self.components_types.get(tp) {
+                Some(set) => sets.push(set),
+                //nothing has this component, so nothing can match
+                None => return,
+            }
+        }
+        //intersect starting from the smallest set
+        sets.sort_by_key(|x| x.len());
+        let mut sets = sets.into_iter();
+        let matches = match sets.next() {
+            Some(first) => sets.fold(first.clone(), |x, y| {
+                x.par_intersection(y).copied().collect()
+            }),
+            None => return,
+        };
+        let excluded = query
+            .without
+            .iter()
+            .filter_map(|x| self.components_types.get(x))
+            .collect::<Vec<_>>();
+        matches.into_iter().for_each(|x| {
+            if excluded.iter().any(|set| set.contains(&x)) {
+                return;
+            }
+            if let Some(mut components) = self.entity_components(x) {
+                visit(x, &mut components);
+            }
+        });
+    }
+}
@@ -0,0 +1,164 @@
+use std::marker::PhantomData;
+
+use crate::{
+    component::{self, ComponentType, TypedComponent, UntypedComponent},
+    entity_id, query,
+};
+
+//components fetched together from an entity, e.g. (&Position, &mut Velocity, Option<&Name>)
+pub trait QueryData {
+    //the entity's components, owned while the item borrows them
+    type State;
+    type Item<'a>;
+    fn register(builder: query::QueryBuilder) -> query::QueryBuilder;
+    fn register_optional(builder: query::QueryBuilder) -> query::QueryBuilder;
+    //None if the entity is missing a required component
+    fn take(group: &query::ComponentGroup) -> Option<Self::State>;
+    fn item(state: &mut Self::State) -> Self::Item<'_>;
+    fn write_back(state: Self::State, group: &mut query::ComponentGroup);
+}
+
+impl<T: ComponentType> QueryData for &T {
+    type State = UntypedComponent;
+    type Item<'a> = &'a T;
+    fn register(builder: query::QueryBuilder) -> query::QueryBuilder {
+        builder.read::<T>()
+    }
+    fn register_optional(builder: query::QueryBuilder) -> query::QueryBuilder {
+        builder.optional_read::<T>()
+    }
+    fn take(group: &query::ComponentGroup) -> Option<Self::State> {
+        group.component(component::type_id::<T>()).cloned()
+    }
+    fn item(state: &mut Self::State) -> Self::Item<'_> {
+        state.get_unchecked::<T>()
+    }
+    fn write_back(_: Self::State, _: &mut query::ComponentGroup) {}
+}
+
+//mutable components are copied on first write, so untouched ones aren't marked as changed
+impl<T: ComponentType> QueryData for &mut T {
+    type State = TypedComponent<T>;
+    type Item<'a> = &'a mut TypedComponent<T>;
+    fn register(builder: query::QueryBuilder) -> query::QueryBuilder {
+        builder.with::<T>()
+    }
+    fn register_optional(builder: query::QueryBuilder) -> query::QueryBuilder {
+        builder.optional::<T>()
+    }
+    fn take(group: &query::ComponentGroup) -> Option<Self::State> {
+        group
+            .component(component::type_id::<T>())
+            .cloned()
+            .map(TypedComponent::new)
+    }
+    fn item(state: &mut Self::State) -> Self::Item<'_> {
+        state
+    }
+    fn write_back(state: Self::State, group: &mut query::ComponentGroup) {
+        group.write_back(state.get_untyped());
+    }
+}
+
+impl<D: QueryData> QueryData for Option<D> {
+    type State = Option<D::State>;
+    type Item<'a> = Option<D::Item<'a>>;
+    fn register(builder: query::QueryBuilder) -> query::QueryBuilder {
+        D::register_optional(builder)
+    }
+    fn register_optional(builder: query::QueryBuilder) -> query::QueryBuilder {
+        D::register_optional(builder)
+    }
+    fn take(group: &query::ComponentGroup) -> Option<Self::State> {
+        Some(D::take(group))
+    }
+    fn item(state: &mut Self::State) -> Self::Item<'_> {
+        state.as_mut().map(D::item)
+    }
+    fn write_back(state: Self::State, group: &mut query::ComponentGroup) {
+        if let Some(state) = state {
+            D::write_back(state, group);
+        }
+    }
+}
+
+macro_rules! impl_query_data {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: QueryData),*> QueryData for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'a> = ($($name::Item<'a>,)*);
+            fn register(builder: query::QueryBuilder) -> query::QueryBuilder {
+                $(let builder = $name::register(builder);)*
+                builder
+            }
+            fn register_optional(builder: query::QueryBuilder) -> query::QueryBuilder {
+                $(let builder = $name::register_optional(builder);)*
+                builder
+            }
+            fn take(group: &query::ComponentGroup) -> Option<Self::State> {
+                Some(($($name::take(group)?,)*))
+            }
+            fn item(state: &mut Self::State) -> Self::Item<'_> {
+                let ($($name,)*) = state;
+                ($($name::item($name),)*)
+            }
+            fn write_back(state: Self::State, group: &mut query::ComponentGroup) {
+                let ($($name,)*) = state;
+                $($name::write_back($name, group);)*
+            }
+        }
+    };
+}
+
+impl_query_data!(A);
+impl_query_data!(A, B);
+impl_query_data!(A, B, C);
+impl_query_data!(A, B, C, D);
+impl_query_data!(A, B, C, D, E);
+impl_query_data!(A, B, C, D, E, F);
+impl_query_data!(A, B, C, D, E, F, G);
+impl_query_data!(A, B, C, D, E, F, G, H);
+
+//a typed query, e.g. Query<(&Position, &mut Velocity)>
+pub struct Query<D: QueryData> {
+    data: PhantomData<fn() -> D>,
+}
+
+impl<D: QueryData> Query<D> {
+    pub fn new() -> Self {
+        Query { data: PhantomData }
+    }
+    //a builder with D's components and access, for adding filters
+    pub fn builder(&self) -> query::QueryBuilder {
+        D::register(query::QueryBuilder::new())
+    }
+    pub fn build(&self) -> query::Query {
+        self.builder().build()
+    }
+    //panics if D writes a component the system's query doesn't declare as written
+    pub fn for_each(
+        &self,
+        result: &mut query::QueryResult,
+        mut f: impl FnMut(entity_id::EntityId, D::Item<'_>),
+    ) {
+        if let Some(writes) = result.writes() {
+            assert!(
+                self.build().writes.is_subset(writes),
+                "typed query writes components the system's query doesn't declare as written"
+            );
+        }
+        for group in result.iter() {
+            if let Some(mut state) = D::take(group) {
+                f(group.get_id(), D::item(&mut state));
+                D::write_back(state, group);
+            }
+        }
+    }
+}
+
+impl<D: QueryData> Default for Query<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
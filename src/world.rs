@@ -1,94 +1,152 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
-    component, entity_builder,
+    base_components, component, component_registry, entity_builder,
     entity_id::{self},
+    events, hashing,
     hook::{self, ChangeHook},
+    observer,
     query::{self, Change},
-    resource, resource_writer, stage,
+    resource, resource_writer, schedule, snapshot, stage, storage,
 };
 use hashbrown::{HashMap, HashSet};
+use rand::{rngs::StdRng, SeedableRng};
 use rayon::prelude::*;
 
 #[derive(Clone, Debug)]
 pub enum WorldError {
     ResourceNotFound,
     EntityNotFound,
+    ComponentNotFound,
+    EntitiesNotFound(Vec<entity_id::EntityId>),
+    LoaderNotFound,
+    UnloaderNotFound,
+    ComponentNotRegistered(component::ComponentTypeId),
+    SerializationError(String),
+    //the unloader's backing store failed
+    StoreError(String),
+    //another generation of the id's slot is alive, e.g. a stored entity whose id was reissued
+    EntityIdConflict(entity_id::EntityId),
+    //the allocator has no slot indexes left in its range
+    EntityRangeExhausted,
+    //indexes of two systems in a stage and the components they conflict on
+    SystemConflict(usize, usize, Vec<component::ComponentTypeId>),
+    //indexes of systems in a stage whose before/after dependencies form a cycle
+    SystemOrderCycle(Vec<usize>),
+    //a validator refused a change
+    InvalidChange(String),
+    //changes and resource writes rejected while applying a stage
+    ChangesRejected(Vec<WorldError>),
+    //names of the hooks that kept causing changes past the depth limit, outermost first
+    ChangeDepthExceeded(Vec<String>),
 }
 
 pub struct World {
-    components: HashMap<component::ComponentInstanceId, component::UntypedComponent>,
-    entities: HashMap<entity_id::EntityId, HashSet<component::ComponentInstanceId>>,
-    components_types: HashMap<component::ComponentTypeId, HashSet<entity_id::EntityId>>,
+    storage: Box<dyn storage::ComponentStorage>,
     resources: HashMap<u64, resource::UntypedResource>,
     //change_trackers: HashMap<component::ComponentTypeId, Vec<component::ComponentInstanceId>>,
     loader: Option<Arc<Mutex<Box<dyn hook::Loader>>>>,
     hooks: Vec<ChangeHook>,
+    validators: Vec<hook::ChangeValidator>,
+    //how deep hooks may cause changes that cause further changes
+    change_depth_limit: usize,
+    observers: observer::Observers,
+    event_updaters: Vec<fn(&mut World)>,
     unloader: Option<Arc<Mutex<Box<dyn hook::Unloader>>>>,
+    registry: component_registry::ComponentRegistry,
+    allocator: Arc<parking_lot::Mutex<entity_id::EntityAllocator>>,
+    seed: Option<u64>,
+    rng: parking_lot::Mutex<StdRng>,
+    stage_runs: u64,
+    change_tick: u64,
+    //id of the system whose changes and commands are being applied, 0 outside of stages
+    change_source: u64,
+    component_ticks: HashMap<component::ComponentInstanceId, component::ComponentTicks>,
 }
 
 impl World {
     pub fn query_world(&self, query: query::Query) -> query::QueryResult {
-        let mut query_result_builder = query::QueryResultBuilder::new();
-        let mut i = query.components.iter();
-        match (i.next(), i.len() != 0) {
-            (Some(first), false) => {
-                if let Some(map) = self.components_types.get(first) {
-                    map.iter().for_each(|id| {
-                        let mut components = Vec::new();
-                        if let Some(set) = self.entities.get(id) {
-                            set.iter().for_each(|component_id| {
-                                if let Some(component) = self.components.get(component_id) {
-                                    components.push(component.clone());
-                                }
-                            });
-                        }
-                        query_result_builder.with_entity(components, *id);
-                    });
-                }
-            }
-            (Some(first), true) => {
-                let first_intersection: HashSet<_> = self
-                    .components_types
-                    .get(first)
-                    .unwrap()
-                    .intersection(self.components_types.get(i.next().unwrap()).unwrap())
-                    .copied()
-                    .collect();
-                let matches = i.fold(first_intersection, |x, y| {
-                    if let Some(component_type_list) = self.components_types.get(y) {
-                        x.par_intersection(component_type_list)
-                            .map(|x| *x)
-                            .collect()
-                    } else {
-                        x
-                    }
-                });
-                matches.iter().for_each(|x| {
-                    query_result_builder.with_entity(
-                        self.entities
-                            .get(x)
-                            .expect("ECS invarient broken")
-                            .iter()
-                            .map(|x| self.components.get(x).unwrap().clone()),
-                        *x,
-                    );
-                });
+        self.query_world_since(query, 0, 0)
+    }
+
+    //added and changed filters match components changed after the given change tick by
+    //anything but the system with the given id
+    pub(crate) fn query_world_since(
+        &self,
+        query: query::Query,
+        last_run: u64,
+        system: u64,
+    ) -> query::QueryResult {
+        let mut query_result_builder = query::QueryResultBuilder::new(self.allocator.clone())
+            .since(last_run, system)
+            .access(&query);
+        let change_filtered = query.is_change_filtered();
+        self.storage.query(&query, &mut |id, components| {
+            if change_filtered && !self.passes_change_filters(&query, id, last_run, system) {
+                return;
             }
-            _ => {}
+            let components = components
+                .filter(|x| query.fetches(x.get_type()))
+                .cloned()
+                .collect::<Vec<_>>();
+            let ticks = components
+                .iter()
+                .filter_map(|x| Some((x.get_type(), *self.component_ticks.get(&x.id())?)))
+                .collect();
+            query_result_builder.with_tracked_entity(components, id, ticks);
+        });
+        let mut result = query_result_builder.build();
+        //storage order isn't stable between runs
+        if self.seed.is_some() {
+            result.sort_by_id();
         }
-        query_result_builder.build()
+        result
+    }
+
+    fn passes_change_filters(
+        &self,
+        query: &query::Query,
+        id: entity_id::EntityId,
+        last_run: u64,
+        system: u64,
+    ) -> bool {
+        let ticks = |tp: &component::ComponentTypeId| {
+            self.component_ticks
+                .get(&component::ComponentInstanceId::new_explicit(id, *tp))
+        };
+        query
+            .added
+            .iter()
+            .all(|x| ticks(x).is_some_and(|x| x.is_added_by_other_since(last_run, system)))
+            && query
+                .changed
+                .iter()
+                .all(|x| ticks(x).is_some_and(|x| x.is_changed_by_other_since(last_run, system)))
     }
 
     pub fn new() -> Self {
         World {
-            components: HashMap::new(),
-            entities: HashMap::new(),
-            components_types: HashMap::new(),
+            storage: storage::new_storage(storage::StorageMode::Sparse),
             resources: HashMap::new(),
             hooks: Vec::new(),
+            validators: Vec::new(),
+            change_depth_limit: 64,
+            observers: observer::Observers::default(),
+            event_updaters: Vec::new(),
             loader: None,
             unloader: None,
+            registry: component_registry::ComponentRegistry::new(),
+            allocator: Arc::new(parking_lot::Mutex::new(entity_id::EntityAllocator::new())),
+            seed: None,
+            rng: parking_lot::Mutex::new(StdRng::from_entropy()),
+            stage_runs: 0,
+            //systems that never ran have a last run of 0, so everything is newer than that
+            change_tick: 1,
+            change_source: 0,
+            component_ticks: HashMap::new(),
         }
     }
 
@@ -96,22 +154,45 @@ impl World {
         &self,
         id: component::ComponentInstanceId,
     ) -> Option<&component::UntypedComponent> {
-        self.components.get(&id)
+        self.storage.get(id)
     }
 
     pub fn get_all_components_of_entity(
         &self,
         id: entity_id::EntityId,
     ) -> Option<Vec<component::UntypedComponent>> {
-        self.entities.get(&id).map(|x| {
-            x.iter()
-                .map(|x| self.components.get(x).unwrap().clone())
-                .collect()
-        })
+        self.storage
+            .entity_components(id)
+            .map(|x| x.cloned().collect())
+    }
+
+    pub fn registry(&self) -> &component_registry::ComponentRegistry {
+        &self.registry
+    }
+
+    //whether the entity has components in memory, unloaded entities don't
+    pub fn contains_entity(&self, id: entity_id::EntityId) -> bool {
+        self.storage.contains_entity(id)
+    }
+
+    //false once the entity is despawned, even if its slot is reused by a new entity
+    pub fn is_alive(&self, id: entity_id::EntityId) -> bool {
+        self.allocator.lock().is_alive(id)
+    }
+
+    //the allocator's state, to persist alongside unloaded entities so their ids aren't
+    //reissued after a restart. see WorldBuilder::with_allocator
+    pub fn allocator(&self) -> entity_id::EntityAllocator {
+        self.allocator.lock().clone()
+    }
+
+    //gives up len unused slot indexes of this world's id range, e.g. for another shard
+    pub fn reserve_entity_range(&self, len: u32) -> Option<Range<u32>> {
+        self.allocator.lock().reserve_range(len)
     }
 
     pub fn number_of_entities(&self) -> usize {
-        self.entities.len()
+        self.storage.number_of_entities()
     }
 
     pub fn read_resource<R: resource::Resource + 'static, ReturnType>(
@@ -136,141 +217,752 @@ impl World {
         }
     }
 
+    //returns the resource it replaced
+    pub fn insert_resource<R: resource::Resource + 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .insert(
+                resource::get_resource_id::<R>(),
+                resource::UntypedResource::new(resource),
+            )
+            .map(|x| x.into_inner::<R>())
+    }
+
+    pub fn remove_resource<R: resource::Resource + 'static>(&mut self) -> Result<(), WorldError> {
+        self.take_resource::<R>().map(|_| ())
+    }
+
+    pub fn take_resource<R: resource::Resource + 'static>(&mut self) -> Result<R, WorldError> {
+        self.resources
+            .remove(&resource::get_resource_id::<R>())
+            .map(|x| x.into_inner::<R>())
+            .ok_or(WorldError::ResourceNotFound)
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    //the world's own random stream, for use outside of systems
+    pub fn with_rng<ReturnType>(
+        &self,
+        closure: impl FnOnce(&mut StdRng) -> ReturnType,
+    ) -> ReturnType {
+        closure(&mut self.rng.lock())
+    }
+
+    fn system_rng(&self, index: usize) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(hashing::combine_hash(&[
+                seed,
+                self.stage_runs,
+                index as u64,
+            ])),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    //changes rejected by validators are skipped and logged
     pub fn execute_stage(&mut self, stage: &stage::Stage) {
-        stage
+        if let Err(err) = self.try_execute_stage(stage) {
+            tracing::warn!("stage changes rejected: {:?}", err);
+        }
+    }
+
+    //applies every change that isn't rejected, then reports the rejected ones
+    pub fn try_execute_stage(&mut self, stage: &stage::Stage) -> Result<(), WorldError> {
+        self.stage_runs += 1;
+        //every stage advances the tick, even an empty one
+        if stage.batches().is_empty() {
+            self.change_tick += 1;
+        }
+        let mut errors = Vec::new();
+        for batch in stage.batches() {
+            self.execute_batch(stage, batch, &mut errors);
+        }
+        self.flush_changes();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(WorldError::ChangesRejected(errors))
+        }
+    }
+
+    fn execute_batch(
+        &mut self,
+        stage: &stage::Stage,
+        batch: &[usize],
+        errors: &mut Vec<WorldError>,
+    ) {
+        //the systems record the current tick as their last run, their changes are stamped
+        //with the next one so the other systems of the batch see them as new
+        let this_run = self.change_tick;
+        let run = |i: usize| {
+            let system = stage.system(i);
+            let mut query_res =
+                self.query_world_since(system.query(), stage.last_run(i), stage.system_id(i));
+            stage.set_last_run(i, this_run);
+            query_res.set_rng(self.system_rng(i));
+            system.execute(&mut query_res, self);
+            (stage.system_id(i), query_res)
+        };
+        //seeded worlds run systems in order so spawned entity ids are reproducible
+        let batch = batch
             .iter()
-            .map(|x| {
-                let mut query_res = self.query_world(x.query());
-                x.execute(&mut query_res, self);
-                query_res
-                //stupidness below, can't convert par_iter to iter easily
-            })
-            .collect::<Vec<_>>()
+            .copied()
+            .filter(|i| stage.should_run(*i, self))
+            .collect::<Vec<_>>();
+        let results = if self.seed.is_some() {
+            batch.into_iter().map(run).collect::<Vec<_>>()
+        } else {
+            batch.into_par_iter().map(run).collect::<Vec<_>>()
+        };
+        self.change_tick += 1;
+        //the changes of the whole batch are processed together, then its spawns and commands.
+        //each change remembers the system that made it
+        let mut changes = Vec::new();
+        let mut spawns = Vec::new();
+        let mut commands = Vec::new();
+        for (system, res) in results {
+            let (res_changes, removals, res_spawns, res_commands, cmds) = res.dissolve();
+            changes.extend(
+                removals
+                    .into_iter()
+                    .filter_map(|x| self.storage.get(x).cloned())
+                    .map(|x| query::Change::new(x, query::ChangeType::RemoveComponent))
+                    .chain(res_changes)
+                    .map(|x| (x, system)),
+            );
+            spawns.extend(res_spawns.into_iter().map(|x| (x, system)));
+            commands.push((system, res_commands, cmds));
+        }
+        //systems only see the components they fetched, so whether a write adds or updates
+        //a component is decided against storage
+        for (change, _) in changes.iter_mut() {
+            if matches!(
+                change.1,
+                query::ChangeType::AddComponent | query::ChangeType::UpdateComponent
+            ) {
+                change.1 = if self.storage.get(change.0.id()).is_some() {
+                    query::ChangeType::UpdateComponent
+                } else {
+                    query::ChangeType::AddComponent
+                };
+            }
+        }
+        errors.extend(self.run_changes(changes, false));
+        for (components, system) in spawns {
+            errors.extend(self.spawn_components(components, system).err());
+        }
+        for (system, commands, cmds) in commands {
+            self.change_source = system;
+            errors.extend(commands.apply(self));
+            self.change_source = 0;
+            errors.extend(self.execute_writes(cmds));
+        }
+    }
+
+    //receives the changes applied to the world that match the filter, sent after each
+    //stage or flush_changes call
+    pub fn subscribe(
+        &mut self,
+        filter: observer::ChangeFilter,
+    ) -> std::sync::mpsc::Receiver<observer::ChangeEvent> {
+        self.observers.subscribe(filter)
+    }
+
+    //sends changes applied outside of stages to subscribers
+    pub fn flush_changes(&mut self) {
+        self.observers.flush();
+    }
+
+    //runs one tick of the schedule
+    pub fn run_schedule(&mut self, schedule: &schedule::Schedule) {
+        schedule.run(self);
+        self.update_events();
+    }
+
+    pub fn send_event<E: Send + Sync + 'static>(&mut self, event: E) -> Result<(), WorldError> {
+        self.write_resource(|events: &mut events::Events<E>| events.send(event))
+    }
+
+    //starts a new tick for every registered event type, dropping events from two ticks ago.
+    //run_schedule does this at the end of each tick
+    pub fn update_events(&mut self) {
+        for update in self.event_updaters.clone() {
+            update(self);
+        }
+    }
+
+    //loads entities through the loader, skipping ones already in memory.
+    //nothing is loaded if the loader doesn't know one of the ids. if the loader fails to
+    //forget its copies afterwards the entities stay loaded and the error is returned
+    pub fn load(
+        &mut self,
+        ids: Vec<entity_id::EntityId>,
+    ) -> Result<Vec<entity_id::EntityId>, WorldError> {
+        let loader = self.loader.clone().ok_or(WorldError::LoaderNotFound)?;
+        let mut requested = HashSet::new();
+        let ids = ids
             .into_iter()
-            .for_each(|res| {
-                let (changes, cmds) = res.dissolve();
-                changes.into_iter().for_each(|x| self.execute_change(x));
-                self.execute_command(cmds);
-            });
+            .filter(|x| !self.storage.contains_entity(*x) && requested.insert(*x))
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(ids);
+        }
+        if let Some(id) = ids.iter().find(|x| self.allocator.lock().conflicts(**x)) {
+            return Err(WorldError::EntityIdConflict(*id));
+        }
+        let components = loader.lock().unwrap().load(ids.clone(), self)?;
+        let loaded = components
+            .iter()
+            .map(|x| x.entity_id())
+            .collect::<HashSet<_>>();
+        let missing = ids
+            .iter()
+            .filter(|x| !loaded.contains(*x))
+            .copied()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(WorldError::EntitiesNotFound(missing));
+        }
+        self.execute_changes(
+            components
+                .into_iter()
+                .filter(|x| requested.contains(&x.entity_id()))
+                .map(|x| query::Change::new(x, query::ChangeType::AddComponent)),
+        )?;
+        loader.lock().unwrap().forget(ids.clone(), self)?;
+        Ok(ids)
     }
 
-    pub fn load(&mut self, _: Vec<entity_id::EntityId>) -> Vec<entity_id::EntityId> {
-        todo!()
+    //hands each entity's components to the unloader, then pages them out of memory with
+    //UnloadComponent changes so hooks can tell them apart from removals. if the unloader
+    //fails the entities stay loaded
+    pub fn unload(
+        &mut self,
+        ids: Vec<entity_id::EntityId>,
+    ) -> Result<Vec<entity_id::EntityId>, WorldError> {
+        let unloader = self.unloader.clone().ok_or(WorldError::UnloaderNotFound)?;
+        let mut requested = HashSet::new();
+        let ids = ids
+            .into_iter()
+            .filter(|x| requested.insert(*x))
+            .collect::<Vec<_>>();
+        let missing = ids
+            .iter()
+            .filter(|x| !self.storage.contains_entity(**x))
+            .copied()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(WorldError::EntitiesNotFound(missing));
+        }
+        let components = ids
+            .iter()
+            .filter_map(|x| self.get_all_components_of_entity(*x))
+            .flatten()
+            .collect::<Vec<_>>();
+        //nothing is paged out unless the unloader stored it
+        unloader.lock().unwrap().hook(components.clone(), self)?;
+        self.execute_changes(
+            components
+                .into_iter()
+                .map(|x| query::Change::new(x, query::ChangeType::UnloadComponent)),
+        )?;
+        Ok(ids)
     }
 
-    pub fn execute_command(&mut self, command: resource_writer::ResourceWriter) {
-        command.get_resource_writes().into_iter().for_each(|x| {
-            x(self);
-        });
+    //runs every write, returning the first that failed
+    pub fn execute_command(
+        &mut self,
+        command: resource_writer::ResourceWriter,
+    ) -> Result<(), WorldError> {
+        self.execute_writes(command)
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    fn execute_writes(&mut self, command: resource_writer::ResourceWriter) -> Vec<WorldError> {
+        command
+            .get_resource_writes()
+            .into_iter()
+            .filter_map(|x| x(self).err())
+            .collect()
     }
 
     pub fn get_component<T: component::ComponentType + 'static>(
         &self,
         id: entity_id::EntityId,
     ) -> Option<&T> {
-        self.entities
-            .get(&id)
-            .and_then(|x| x.get(&component::ComponentInstanceId::new::<T>(id)))
-            .and_then(|x| self.components.get(x).and_then(|x| x.get::<T>()))
+        self.storage
+            .get(component::ComponentInstanceId::new::<T>(id))
+            .and_then(|x| x.get::<T>())
     }
 
-    fn execute_change(&mut self, change: Change) {
-        //execute hooks
-        let cngs = self
-            .hooks
-            .iter()
-            .flat_map(|x| {
-                let mut res_writer = resource_writer::ResourceWriter::new();
-                match x.get_type() {
-                    Some(tp)  if change.0.get_type() == tp => {
-                        x.execute(&change, self, &mut res_writer)
+    //the world change ticks at which the entity's T was added and last updated
+    pub fn get_component_ticks<T: component::ComponentType + 'static>(
+        &self,
+        id: entity_id::EntityId,
+    ) -> Option<component::ComponentTicks> {
+        self.component_ticks
+            .get(&component::ComponentInstanceId::new::<T>(id))
+            .copied()
+    }
+
+    //changes are stamped with it, it advances when a batch of systems has run, before the
+    //batch's changes are applied
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    fn execute_change(&mut self, change: Change) -> Result<(), WorldError> {
+        self.execute_changes(std::iter::once(change))
+    }
+
+    //applies every change, returning the first rejection
+    fn execute_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<(), WorldError> {
+        self.process_changes(changes)
+            .into_iter()
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    fn process_changes(&mut self, changes: impl IntoIterator<Item = Change>) -> Vec<WorldError> {
+        let source = self.change_source;
+        self.run_changes(changes.into_iter().map(|x| (x, source)), false)
+    }
+
+    //applies the changes and every change their hooks cause from a work queue, each change
+    //after the ones its hooks caused. a rejected change is skipped along with the changes
+    //its hooks would cause, as are changes nested deeper than the depth limit. the hooks'
+    //resource writes run afterwards in the order the hooks ran. changes are paired with the
+    //id of the system they come from, which the changes they cause inherit.
+    //a spawn is atomic, any rejection rolls back every change of the cascade and drops the
+    //hooks' resource writes. the spawned entity's own components are placed together once
+    //everything their hooks caused is applied
+    fn run_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = (Change, u64)>,
+        spawn: bool,
+    ) -> Vec<WorldError> {
+        enum Work {
+            //the indexes of the hooks that caused the change
+            Visit(Change, u64, Vec<usize>),
+            Apply(Change, u64),
+            //places the spawned entity's components, which collect in spawned
+            Place(u64),
+        }
+        let changes = changes.into_iter().collect::<Vec<_>>();
+        let place = changes.first().map(|(_, source)| Work::Place(*source));
+        let mut queue = changes
+            .into_iter()
+            .map(|(x, source)| Work::Visit(x, source, Vec::new()))
+            .chain(place.filter(|_| spawn))
+            .collect::<Vec<_>>();
+        queue.reverse();
+        let mut spawned = Vec::new();
+        let mut writers = Vec::new();
+        let mut errors = Vec::new();
+        let mut undo = Vec::new();
+        let pending = self.observers.pending();
+        while let Some(work) = queue.pop() {
+            let (change, source, chain) = match work {
+                Work::Apply(change, source) => {
+                    self.apply_changes(vec![change], source, &mut undo);
+                    if !spawn {
+                        undo.clear();
                     }
-                    None => x.execute(&change, self, &mut res_writer),
-                    _ => vec![],
+                    continue;
                 }
-            })
-            .collect::<Vec<_>>();
-        cngs.into_iter().for_each(|x| self.execute_change(x));
-        match change {
-            query::Change(
-                comp,
-                _removed_or_unloaded @ (query::ChangeType::RemoveComponent
-                | query::ChangeType::UnloadComponent),
-            ) => {
-                let tid = &&comp.get_type();
-                let eid = &comp.id().entity_id();
-                let id = &comp.id();
-                if let Some(set) = self.entities.get_mut(eid) {
-                    set.remove(id);
-                    if set.is_empty() {
-                        self.entities.remove(eid);
+                Work::Place(source) => {
+                    self.apply_changes(std::mem::take(&mut spawned), source, &mut undo);
+                    continue;
+                }
+                Work::Visit(change, source, chain) => (change, source, chain),
+            };
+            if chain.len() > self.change_depth_limit {
+                let hooks = chain
+                    .iter()
+                    .map(|x| self.hooks[*x].name().to_string())
+                    .collect::<Vec<_>>();
+                tracing::warn!(?hooks, "change depth limit exceeded");
+                errors.push(WorldError::ChangeDepthExceeded(hooks));
+                continue;
+            }
+            if matches!(
+                change.1,
+                query::ChangeType::AddComponent | query::ChangeType::UpdateComponent
+            ) && self.allocator.lock().conflicts(change.0.entity_id())
+            {
+                errors.push(WorldError::EntityIdConflict(change.0.entity_id()));
+                continue;
+            }
+            let mut change = match self
+                .validators
+                .iter()
+                .try_fold(change, |change, x| x.validate(change, self))
+            {
+                Ok(change) => change,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            change.2 = self.storage.get(change.0.id()).cloned();
+            let mut caused = Vec::new();
+            for (i, hook) in self.hooks.iter().enumerate() {
+                if hook.get_type().is_none_or(|tp| tp == change.0.get_type()) {
+                    let mut res_writer = resource_writer::ResourceWriter::new();
+                    caused.extend(
+                        hook.execute(&change, self, &mut res_writer)
+                            .into_iter()
+                            .map(|x| (x, i)),
+                    );
+                    writers.push(res_writer);
+                }
+            }
+            if spawn && chain.is_empty() {
+                spawned.push(change);
+            } else {
+                queue.push(Work::Apply(change, source));
+            }
+            queue.extend(caused.into_iter().rev().map(|(x, i)| {
+                let mut chain = chain.clone();
+                chain.push(i);
+                Work::Visit(x, source, chain)
+            }));
+        }
+        if spawn && !errors.is_empty() {
+            self.rollback(undo);
+            self.observers.discard(pending);
+            return errors;
+        }
+        for writer in writers {
+            errors.extend(self.execute_writes(writer));
+        }
+        errors
+    }
+
+    //records what they replace in undo. added and updated components go into storage
+    //together, so an entity's components move between archetypes once
+    fn apply_changes(&mut self, changes: Vec<Change>, source: u64, undo: &mut Vec<Undo>) {
+        let mut inserted = Vec::new();
+        for query::Change(comp, change_type, _) in changes {
+            let eid = comp.entity_id();
+            undo.push(Undo::Component(
+                comp.id(),
+                self.storage.get(comp.id()).cloned(),
+                self.component_ticks.get(&comp.id()).copied(),
+            ));
+            match change_type {
+                query::ChangeType::RemoveComponent => {
+                    self.component_ticks.remove(&comp.id());
+                    self.storage.remove(comp.id());
+                    //despawned entities give their slot back, unloaded ones keep it
+                    if !self.storage.contains_entity(eid) && self.allocator.lock().is_alive(eid) {
+                        self.allocator.lock().free(eid);
+                        undo.push(Undo::Freed(eid));
                     }
+                    self.observers.record(&comp, change_type, None);
                 }
-                if let Some(set) = self.components_types.get_mut(tid) {
-                    set.remove(eid);
-                    if set.is_empty() {
-                        self.entities.remove(eid);
+                query::ChangeType::UnloadComponent => {
+                    self.component_ticks.remove(&comp.id());
+                    self.storage.remove(comp.id());
+                    self.observers.record(&comp, change_type, None);
+                }
+                query::ChangeType::AddComponent | query::ChangeType::UpdateComponent => {
+                    //conflicting ids were rejected before the change got here
+                    if !self.storage.contains_entity(eid) && !self.allocator.lock().is_alive(eid) {
+                        self.allocator.lock().claim(eid);
+                        undo.push(Undo::Claimed(eid));
                     }
+                    let tick = self.change_tick;
+                    self.component_ticks
+                        .entry(comp.id())
+                        .and_modify(|x| {
+                            x.changed = tick;
+                            x.changed_by = source;
+                        })
+                        .or_insert(component::ComponentTicks {
+                            added: tick,
+                            changed: tick,
+                            added_by: source,
+                            changed_by: source,
+                        });
+                    inserted.push((comp, change_type));
                 }
-                self.components.remove(id);
             }
-            query::Change(comp, query::ChangeType::AddComponent) => {
-                let tid = comp.get_type();
-                //execute add component hooks
-                let eid = comp.id().entity_id();
-                let cid = comp.id();
-                self.components.insert(cid, comp);
-                if let Some(set) = self.entities.get_mut(&eid) {
-                    set.insert(cid);
-                } else {
-                    let mut set = HashSet::new();
-                    set.insert(cid);
-                    self.entities.insert(eid, set);
+        }
+        let previous = self
+            .storage
+            .insert_all(inserted.iter().map(|(x, _)| x.clone()).collect());
+        for ((comp, change_type), previous) in inserted.into_iter().zip(previous) {
+            self.observers.record(&comp, change_type, previous);
+        }
+    }
+
+    //a rejected change anywhere in the spawn's cascade undoes all of it, including the
+    //changes hooks made to other entities, and frees the entity's id
+    fn spawn_components(
+        &mut self,
+        components: Vec<component::UntypedComponent>,
+        source: u64,
+    ) -> Result<(), WorldError> {
+        let id = match components.first() {
+            Some(x) => x.entity_id(),
+            None => return Ok(()),
+        };
+        let errors = self.run_changes(
+            components.into_iter().map(|x| {
+                (
+                    query::Change::new(x, query::ChangeType::AddComponent),
+                    source,
+                )
+            }),
+            true,
+        );
+        match errors.into_iter().next() {
+            Some(err) => {
+                self.allocator.lock().free(id);
+                Err(err)
+            }
+            None => Ok(()),
+        }
+    }
+
+    //puts back what apply_change replaced, newest first. hooks don't run
+    fn rollback(&mut self, undo: Vec<Undo>) {
+        for x in undo.into_iter().rev() {
+            match x {
+                Undo::Component(id, component, ticks) => {
+                    match component {
+                        Some(component) => {
+                            self.storage.insert(component);
+                        }
+                        None => {
+                            self.storage.remove(id);
+                        }
+                    }
+                    match ticks {
+                        Some(ticks) => {
+                            self.component_ticks.insert(id, ticks);
+                        }
+                        None => {
+                            self.component_ticks.remove(&id);
+                        }
+                    }
                 }
-                if let Some(set) = self.components_types.get_mut(&tid) {
-                    set.insert(eid);
-                } else {
-                    let mut set = HashSet::new();
-                    set.insert(eid);
-                    self.components_types.insert(tid, set);
+                Undo::Claimed(id) => self.allocator.lock().free(id),
+                Undo::Freed(id) => {
+                    self.allocator.lock().claim(id);
                 }
             }
-            query::Change(comp, query::ChangeType::UpdateComponent) => {
-                let id = comp.id();
-                self.components.insert(id, comp);
+        }
+    }
+
+    fn snapshot<T: snapshot::SnapshotData>(&self) -> Result<snapshot::Snapshot<T>, WorldError> {
+        let entities = self
+            .storage
+            .entities()
+            .map(|id| {
+                Ok(snapshot::SnapshotEntity {
+                    id,
+                    components: self
+                        .storage
+                        .entity_components(id)
+                        .expect("ECS invarient broken")
+                        .map(|x| {
+                            T::from_component(&self.registry, x).map(|data| (x.get_type(), data))
+                        })
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let resources = self
+            .registry
+            .serializable_resources()
+            .filter_map(|id| self.resources.get(&id).map(|x| (id, x)))
+            .map(|(id, x)| T::from_resource(&self.registry, id, x).map(|data| (id, data)))
+            .collect::<Result<_, _>>()?;
+        Ok(snapshot::Snapshot {
+            entities,
+            resources,
+            allocator: Some(self.allocator.lock().clone()),
+        })
+    }
+
+    //writes every entity and serializable resource out, see WorldBuilder::from_snapshot
+    pub fn save_snapshot(
+        &self,
+        writer: impl std::io::Write,
+        format: snapshot::SnapshotFormat,
+    ) -> Result<(), WorldError> {
+        match format {
+            snapshot::SnapshotFormat::Cbor => {
+                serde_cbor::to_writer(writer, &self.snapshot::<serde_cbor::Value>()?)
+                    .map_err(|e| WorldError::SerializationError(e.to_string()))
+            }
+            snapshot::SnapshotFormat::Json => {
+                serde_json::to_writer_pretty(writer, &self.snapshot::<serde_json::Value>()?)
+                    .map_err(|e| WorldError::SerializationError(e.to_string()))
             }
         }
     }
 
+    fn restore_snapshot<T: snapshot::SnapshotData>(
+        &mut self,
+        snapshot: snapshot::Snapshot<T>,
+    ) -> Result<(), WorldError> {
+        if let Some(allocator) = snapshot.allocator {
+            *self.allocator.lock() = allocator;
+        }
+        for (id, data) in snapshot.resources {
+            let resource = data.into_resource(&self.registry, id)?;
+            self.resources.insert(id, resource);
+        }
+        let components = snapshot
+            .entities
+            .into_iter()
+            .flat_map(|entity| {
+                let registry = &self.registry;
+                entity
+                    .components
+                    .into_iter()
+                    .map(move |(tp, data)| data.into_component(registry, tp, entity.id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        //added through hooks so derived state like PositionMap is rebuilt
+        self.execute_changes(
+            components
+                .into_iter()
+                .map(|x| query::Change::new(x, query::ChangeType::AddComponent)),
+        )
+    }
+
+    //adds the component to an existing entity, or updates it if the entity already has one
+    pub fn insert_component<T: component::ComponentType>(
+        &mut self,
+        id: entity_id::EntityId,
+        component: T,
+    ) -> Result<(), WorldError> {
+        self.insert_untyped_component(component.into_untyped(id))
+    }
+
+    pub fn insert_untyped_component(
+        &mut self,
+        component: component::UntypedComponent,
+    ) -> Result<(), WorldError> {
+        if !self.storage.contains_entity(component.entity_id()) {
+            return Err(WorldError::EntityNotFound);
+        }
+        let change_type = if self.storage.get(component.id()).is_some() {
+            query::ChangeType::UpdateComponent
+        } else {
+            query::ChangeType::AddComponent
+        };
+        self.execute_change(query::Change::new(component, change_type))
+    }
+
+    //removes and returns the component. removing the last one despawns the entity
+    pub fn remove_component<T: component::ComponentType>(
+        &mut self,
+        id: entity_id::EntityId,
+    ) -> Result<T, WorldError> {
+        self.remove_untyped_component(component::ComponentInstanceId::new::<T>(id))
+            .map(|x| x.get_unchecked::<T>().clone())
+    }
+
+    pub fn remove_untyped_component(
+        &mut self,
+        id: component::ComponentInstanceId,
+    ) -> Result<component::UntypedComponent, WorldError> {
+        if !self.storage.contains_entity(id.entity_id()) {
+            return Err(WorldError::EntityNotFound);
+        }
+        let component = self
+            .storage
+            .get(id)
+            .cloned()
+            .ok_or(WorldError::ComponentNotFound)?;
+        self.execute_change(query::Change::new(
+            component.clone(),
+            query::ChangeType::RemoveComponent,
+        ))?;
+        Ok(component)
+    }
+
     pub fn add_entity(&mut self) -> entity_builder::EntityBuilder {
         entity_builder::EntityBuilder::new(self)
     }
 
+    //rejected removals are logged
     pub fn remove_entity(&mut self, id: entity_id::EntityId) {
-        if let Some(set) = self.entities.get(&id) {
-            let changes = set
-                .iter()
-                .map(|x| {
-                    query::Change(
-                        self.components.get(x).unwrap().clone(),
-                        query::ChangeType::RemoveComponent,
-                    )
-                })
-                .collect::<Vec<_>>();
-            changes.into_iter().for_each(|x| self.execute_change(x));
+        if let Err(err) = self.remove_entity_components(id) {
+            tracing::warn!("failed to remove {}: {:?}", id, err);
         }
     }
+
+    pub fn try_remove_entity(&mut self, id: entity_id::EntityId) -> Result<(), WorldError> {
+        if !self.storage.contains_entity(id) {
+            return Err(WorldError::EntityNotFound);
+        }
+        self.remove_entity_components(id)
+    }
+
+    fn remove_entity_components(&mut self, id: entity_id::EntityId) -> Result<(), WorldError> {
+        let components = self.get_all_components_of_entity(id).unwrap_or_default();
+        self.execute_changes(
+            components
+                .into_iter()
+                .map(|x| query::Change::new(x, query::ChangeType::RemoveComponent)),
+        )
+    }
+
+    //removes the entity along with its children, their children and so on
+    pub fn remove_entity_recursive(&mut self, id: entity_id::EntityId) -> Result<(), WorldError> {
+        if !self.storage.contains_entity(id) {
+            return Err(WorldError::EntityNotFound);
+        }
+        let mut to_visit = vec![id];
+        let mut tree = Vec::new();
+        let mut visited = HashSet::new();
+        while let Some(x) = to_visit.pop() {
+            if visited.insert(x) {
+                if let Some(children) = self.get_component::<base_components::Children>(x) {
+                    to_visit.extend(children.entities.iter().copied());
+                }
+                tree.push(x);
+            }
+        }
+        tree.into_iter()
+            .map(|x| self.remove_entity_components(x))
+            .fold(Ok(()), Result::and)
+    }
 }
 
 impl entity_builder::SpawnLocation for World {
-    fn spawn(&mut self, components: Vec<component::UntypedComponent>) {
-        components
-            .into_iter()
-            .map(|x| query::Change(x, query::ChangeType::AddComponent))
-            .for_each(|change| self.execute_change(change));
+    fn allocate_id(&mut self) -> Option<entity_id::EntityId> {
+        self.allocator.lock().allocate()
+    }
+    fn spawn(&mut self, components: Vec<component::UntypedComponent>) -> Result<(), WorldError> {
+        self.spawn_components(components, self.change_source)
     }
 }
 
+//what a change replaced: the component and its ticks, or the entity's slot
+enum Undo {
+    Component(
+        component::ComponentInstanceId,
+        Option<component::UntypedComponent>,
+        Option<component::ComponentTicks>,
+    ),
+    Claimed(entity_id::EntityId),
+    Freed(entity_id::EntityId),
+}
+
 pub struct WorldBuilder {
     world: World,
 }
@@ -282,12 +974,56 @@ impl WorldBuilder {
         }
     }
     pub fn with_resource<R: resource::Resource + 'static>(mut self, resource: R) -> Self {
-        self.world.resources.insert(
-            resource::get_resource_id::<R>(),
-            resource::UntypedResource::new(resource),
-        );
+        self.world.insert_resource(resource);
+        self
+    }
+    //must be chosen before any entity is added
+    pub fn with_storage(mut self, mode: storage::StorageMode) -> Self {
+        self.world.storage = storage::new_storage(mode);
+        self
+    }
+    //entities spawned in this world only use slot indexes in the range
+    pub fn with_entity_range(self, range: Range<u32>) -> Self {
+        *self.world.allocator.lock() = entity_id::EntityAllocator::with_range(range);
+        self
+    }
+    //restores an allocator saved with World::allocator, e.g. by SledStore
+    pub fn with_allocator(self, allocator: entity_id::EntityAllocator) -> Self {
+        *self.world.allocator.lock() = allocator;
+        self
+    }
+    pub fn with_change_depth_limit(mut self, limit: usize) -> Self {
+        self.world.change_depth_limit = limit;
+        self
+    }
+    //adds an Events<E> resource, which World::update_events advances at the end of each tick
+    pub fn with_event<E: Send + Sync + 'static>(mut self) -> Self {
+        fn update<E: Send + Sync + 'static>(world: &mut World) {
+            let _ = world.write_resource(|events: &mut events::Events<E>| events.update());
+        }
+        self.world.event_updaters.push(update::<E>);
+        self.with_resource(events::Events::<E>::new())
+    }
+    //makes entity ids, system random streams and World::with_rng reproducible between runs
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.world.seed = Some(seed);
+        self.world.rng = parking_lot::Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+    pub fn with_component<T: component::ComponentType>(mut self) -> Self {
+        self.world.registry.register::<T>();
         self
     }
+    //the resource is included in snapshots
+    pub fn with_serializable_resource<
+        R: resource::Resource + serde::Serialize + serde::de::DeserializeOwned + 'static,
+    >(
+        mut self,
+        resource: R,
+    ) -> Self {
+        self.world.registry.register_resource::<R>();
+        self.with_resource(resource)
+    }
     pub fn with_loader(mut self, loader: impl hook::Loader + 'static) -> Self {
         self.world.loader = Some(Arc::new(Mutex::new(Box::new(loader))));
         self
@@ -296,22 +1032,131 @@ impl WorldBuilder {
         self.world.unloader = Some(Arc::new(Mutex::new(Box::new(unloader))));
         self
     }
-    pub fn with_hook(mut self, _hook: hook::HookLambda) -> Self {
-        self.world.hooks.push(hook::ChangeHook::new(_hook));
-        self
+    //runs for every change. takes fns, closures or boxed hook::HookLambdas
+    pub fn with_hook(
+        self,
+        hook: impl Fn(&query::Change, &World, &mut resource_writer::ResourceWriter) -> Vec<query::Change>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.with_prioritized_hook(0, hook)
     }
+    //runs for changes of T
     pub fn with_typed_hook<T: component::ComponentType + 'static>(
+        self,
+        hook: impl Fn(&query::Change, &World, &mut resource_writer::ResourceWriter) -> Vec<query::Change>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.with_typed_prioritized_hook::<T>(0, hook)
+    }
+    //hooks with a higher priority run before ones with a lower priority, which is 0 for
+    //hooks added without one
+    pub fn with_prioritized_hook(
         mut self,
-        _hook: hook::HookLambda,
+        priority: i32,
+        hook: impl Fn(&query::Change, &World, &mut resource_writer::ResourceWriter) -> Vec<query::Change>
+            + Send
+            + Sync
+            + 'static,
     ) -> Self {
-        self.world
+        let name = std::any::type_name_of_val(&hook);
+        self.add_hook(
+            hook::ChangeHook::new(Box::new(hook))
+                .with_priority(priority)
+                .with_name(name),
+        );
+        self
+    }
+    pub fn with_typed_prioritized_hook<T: component::ComponentType + 'static>(
+        mut self,
+        priority: i32,
+        hook: impl Fn(&query::Change, &World, &mut resource_writer::ResourceWriter) -> Vec<query::Change>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        let name = std::any::type_name_of_val(&hook);
+        self.add_hook(
+            hook::ChangeHook::new_typed::<T>(Box::new(hook))
+                .with_priority(priority)
+                .with_name(name),
+        );
+        self
+    }
+    fn add_hook(&mut self, hook: hook::ChangeHook) {
+        let index = self
+            .world
             .hooks
-            .push(hook::ChangeHook::new_typed::<T>(_hook));
+            .iter()
+            .position(|x| x.priority() < hook.priority())
+            .unwrap_or(self.world.hooks.len());
+        self.world.hooks.insert(index, hook);
+    }
+    //validators run before hooks in the order they were added, each seeing the change the
+    //previous one returned
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(query::Change, &World) -> Result<query::Change, WorldError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.world
+            .validators
+            .push(hook::ChangeValidator::new(Box::new(validator)));
+        self
+    }
+    pub fn with_typed_validator<T: component::ComponentType + 'static>(
+        mut self,
+        validator: impl Fn(query::Change, &World) -> Result<query::Change, WorldError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.world
+            .validators
+            .push(hook::ChangeValidator::new_typed::<T>(Box::new(validator)));
+        self
+    }
+    pub fn with_stateful_hook(mut self, hook: impl hook::Hook + 'static) -> Self {
+        self.add_hook(hook::ChangeHook::new_stateful(Box::new(hook), None));
+        self
+    }
+    pub fn with_typed_stateful_hook<T: component::ComponentType + 'static>(
+        mut self,
+        hook: impl hook::Hook + 'static,
+    ) -> Self {
+        self.add_hook(hook::ChangeHook::new_stateful(
+            Box::new(hook),
+            Some(component::type_id::<T>()),
+        ));
         self
     }
     pub fn build(self) -> World {
         self.world
     }
+    //builds the world and fills it from a snapshot written by World::save_snapshot. components
+    //and serializable resources in the snapshot must be registered with this builder
+    pub fn from_snapshot(
+        mut self,
+        reader: impl std::io::Read,
+        format: snapshot::SnapshotFormat,
+    ) -> Result<World, WorldError> {
+        match format {
+            snapshot::SnapshotFormat::Cbor => self.world.restore_snapshot(
+                serde_cbor::from_reader::<snapshot::Snapshot<serde_cbor::Value>, _>(reader)
+                    .map_err(|e| WorldError::SerializationError(e.to_string()))?,
+            ),
+            snapshot::SnapshotFormat::Json => self.world.restore_snapshot(
+                serde_json::from_reader::<_, snapshot::Snapshot<serde_json::Value>>(reader)
+                    .map_err(|e| WorldError::SerializationError(e.to_string()))?,
+            ),
+        }?;
+        Ok(self.world)
+    }
 }
 impl Default for WorldBuilder {
     fn default() -> Self {
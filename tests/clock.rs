@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use melon::*;
+
+//moves every position by the clock's tick
+struct ClockSystem {}
+impl system::System for ClockSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, world: &world::World) {
+        let tick = world.read_resource(|x: &clock::Clock| x.tick()).unwrap();
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x += tick as i32;
+        }
+    }
+}
+
+fn setup() -> (world::World, entity_id::EntityId, stage::Stage) {
+    let mut world = default_world::DefaultWorld::get()
+        .with_resource(clock::Clock::new(Duration::from_millis(10)))
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(ClockSystem {})
+        .build();
+    (world, id, stage1)
+}
+
+fn x(world: &world::World, id: entity_id::EntityId) -> i32 {
+    world
+        .get_component::<base_components::Position>(id)
+        .unwrap()
+        .x
+}
+
+#[test]
+fn run_ticks() {
+    let (mut world, id, stage1) = setup();
+    assert_eq!(runner::run_ticks(&mut world, &stage1, 3).unwrap(), 3);
+    assert_eq!(x(&world, id), 6);
+    world.write_resource(|x: &mut clock::Clock| x.pause()).unwrap();
+    assert_eq!(runner::run_ticks(&mut world, &stage1, 3).unwrap(), 0);
+    world
+        .write_resource(|x: &mut clock::Clock| {
+            x.resume();
+            x.set_time_scale(0.5);
+        })
+        .unwrap();
+    runner::run_ticks(&mut world, &stage1, 2).unwrap();
+    let (tick, elapsed) = world
+        .read_resource(|x: &clock::Clock| (x.tick(), x.elapsed()))
+        .unwrap();
+    assert_eq!(tick, 5);
+    assert_eq!(elapsed, Duration::from_millis(40));
+    assert!(matches!(
+        runner::run_ticks(&mut world::WorldBuilder::new().build(), &stage1, 1),
+        Err(world::WorldError::ResourceNotFound)
+    ));
+}
+
+#[test]
+fn fixed_timestep_catch_up() {
+    let (mut world, _, stage1) = setup();
+    let mut runner = runner::FixedTimestepRunner::new().with_max_catch_up(4);
+    assert_eq!(
+        runner
+            .advance(&mut world, &stage1, Duration::from_millis(25))
+            .unwrap(),
+        2
+    );
+    //the leftover 5ms carry over
+    assert_eq!(
+        runner
+            .advance(&mut world, &stage1, Duration::from_millis(5))
+            .unwrap(),
+        1
+    );
+    //a long stall only catches up on max_catch_up steps
+    assert_eq!(
+        runner
+            .advance(&mut world, &stage1, Duration::from_secs(1))
+            .unwrap(),
+        4
+    );
+    assert_eq!(
+        runner
+            .advance(&mut world, &stage1, Duration::from_millis(9))
+            .unwrap(),
+        0
+    );
+    let tick = world.read_resource(|x: &clock::Clock| x.tick()).unwrap();
+    assert_eq!(tick, 7);
+}
@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+
+use melon::*;
+
+struct Moved(i32);
+
+//sends the x of every position, then moves it along
+struct SendSystem {}
+impl system::System for SendSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        let mut sent = Vec::new();
+        for e in query_result.iter() {
+            let mut position = e.get::<base_components::Position>().unwrap();
+            sent.push(position.x);
+            position.x += 1;
+        }
+        for x in sent {
+            query_result.send_event(Moved(x));
+        }
+    }
+}
+
+//records every event it hasn't read yet
+struct ReadSystem {
+    reader: Mutex<events::EventReader<Moved>>,
+    read: Arc<Mutex<Vec<i32>>>,
+}
+impl system::System for ReadSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, _query_result: &mut query::QueryResult, world: &world::World) {
+        let mut reader = self.reader.lock().unwrap();
+        let read = world
+            .read_resource(|x: &events::Events<Moved>| {
+                x.read(&mut reader).map(|x| x.0).collect::<Vec<_>>()
+            })
+            .unwrap();
+        self.read.lock().unwrap().extend(read);
+    }
+}
+
+#[test]
+fn events_are_double_buffered() {
+    let mut world = default_world::DefaultWorld::get()
+        .with_event::<Moved>()
+        .build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let schedule = schedule::ScheduleBuilder::new()
+        .with_system(schedule::UPDATE, SendSystem {})
+        .build();
+    let sent = |world: &world::World| {
+        world
+            .read_resource(|x: &events::Events<Moved>| x.iter().map(|x| x.0).collect::<Vec<_>>())
+            .unwrap()
+    };
+    //the tick's events stay readable until the end of the next tick
+    world.run_schedule(&schedule);
+    assert_eq!(sent(&world), vec![0]);
+    world.run_schedule(&schedule);
+    assert_eq!(sent(&world), vec![1]);
+    world.send_event(Moved(10)).unwrap();
+    assert_eq!(sent(&world), vec![1, 10]);
+    world.update_events();
+    assert_eq!(sent(&world), vec![10]);
+    world.update_events();
+    assert!(sent(&world).is_empty());
+}
+
+#[test]
+fn readers_see_each_event_once() {
+    let mut world = default_world::DefaultWorld::get()
+        .with_event::<Moved>()
+        .build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(SendSystem {})
+        .build();
+    let read = Arc::new(Mutex::new(Vec::new()));
+    let stage2 = stage::StageBuilder::new()
+        .with_system(ReadSystem {
+            reader: Mutex::new(events::EventReader::new()),
+            read: read.clone(),
+        })
+        .build();
+    for _ in 0..3 {
+        world.execute_stage(&stage1);
+        world.execute_stage(&stage2);
+        world.execute_stage(&stage2);
+        world.update_events();
+    }
+    assert_eq!(*read.lock().unwrap(), vec![0, 1, 2]);
+}
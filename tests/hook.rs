@@ -0,0 +1,392 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc,
+};
+
+use melon::*;
+
+//sends every nth change of the hooked component
+struct EveryNth {
+    n: usize,
+    seen: usize,
+    sender: mpsc::Sender<entity_id::EntityId>,
+}
+impl hook::Hook for EveryNth {
+    fn execute(
+        &mut self,
+        change: &query::Change,
+        _: &world::World,
+        _: &mut resource_writer::ResourceWriter,
+    ) -> Vec<query::Change> {
+        self.seen += 1;
+        if self.seen.is_multiple_of(self.n) {
+            self.sender.send(change.0.entity_id()).unwrap();
+        }
+        Vec::new()
+    }
+}
+
+#[test]
+fn closure_and_stateful_hooks() {
+    let names = Arc::new(AtomicUsize::new(0));
+    let counter = names.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut world = default_world::DefaultWorld::get()
+        .with_typed_hook::<base_components::Name>(move |_, _, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        })
+        .with_typed_stateful_hook::<base_components::Position>(EveryNth {
+            n: 2,
+            seen: 0,
+            sender,
+        })
+        .build();
+    let ids = (0..4)
+        .map(|x| {
+            world
+                .add_entity()
+                .with(base_components::Position { x, y: 0 })
+                .with(base_components::Name {
+                    name: x.to_string(),
+                })
+                .spawn()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(names.load(Ordering::Relaxed), 4);
+    assert_eq!(
+        receiver.try_iter().collect::<Vec<_>>(),
+        vec![ids[1], ids[3]]
+    );
+}
+
+#[test]
+fn hook_priorities() {
+    let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let hook = |name: &'static str| {
+        let order = order.clone();
+        move |_: &query::Change, _: &world::World, _: &mut resource_writer::ResourceWriter| {
+            order.lock().push(name);
+            Vec::new()
+        }
+    };
+    let mut world = world::WorldBuilder::new()
+        .with_hook(hook("default"))
+        .with_prioritized_hook(-1, hook("late"))
+        .with_typed_prioritized_hook::<base_components::Position>(10, hook("early"))
+        .with_hook(hook("default2"))
+        .build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    assert_eq!(*order.lock(), vec!["early", "default", "default2", "late"]);
+}
+
+#[test]
+fn validators() {
+    let mut world = default_world::DefaultWorld::get()
+        .with_typed_validator::<base_components::Parent>(|change, world| {
+            let parent = change.0.get::<base_components::Parent>().unwrap().entity;
+            if world.is_alive(parent) {
+                Ok(change)
+            } else {
+                Err(world::WorldError::EntityNotFound)
+            }
+        })
+        //clamps positions to a 10x10 map
+        .with_typed_validator::<base_components::Position>(|change, _| {
+            let query::Change(component, change_type, _) = change;
+            let mut position = component
+                .get::<base_components::Position>()
+                .unwrap()
+                .clone();
+            position.x = position.x.clamp(0, 9);
+            position.y = position.y.clamp(0, 9);
+            Ok(query::Change::new(
+                component::UntypedComponent::new(position, component.entity_id()),
+                change_type,
+            ))
+        })
+        .build();
+    let clamped = world
+        .add_entity()
+        .with(base_components::Position { x: 20, y: -3 })
+        .spawn();
+    let position = world
+        .get_component::<base_components::Position>(clamped)
+        .unwrap();
+    assert_eq!((position.x, position.y), (9, 0));
+    assert_eq!(
+        world
+            .read_resource(|x: &position_map::PositionMap| x.get_nearest([0, 0], 1))
+            .unwrap(),
+        vec![(clamped, [9, 0])]
+    );
+    let orphan = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "orphan".to_string(),
+        })
+        .with(base_components::Parent {
+            entity: entity_id::EntityId::from_parts(1000, 0),
+        })
+        .try_spawn();
+    assert!(matches!(orphan, Err(world::WorldError::EntityNotFound)));
+    assert_eq!(world.number_of_entities(), 1);
+    assert!(matches!(
+        world.insert_component(
+            clamped,
+            base_components::Parent {
+                entity: entity_id::EntityId::from_parts(1000, 0),
+            }
+        ),
+        Err(world::WorldError::EntityNotFound)
+    ));
+    assert!(world
+        .get_component::<base_components::Parent>(clamped)
+        .is_none());
+}
+
+//gives every positioned entity a parent that doesn't exist
+struct AdoptSystem {}
+impl system::System for AdoptSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        let ids = query_result.iter().map(|x| x.get_id()).collect::<Vec<_>>();
+        for id in ids {
+            query_result.insert_component(
+                id,
+                base_components::Parent {
+                    entity: entity_id::EntityId::from_parts(1000, 0),
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn rejected_commands_are_reported() {
+    let mut world = default_world::DefaultWorld::get()
+        .with_typed_validator::<base_components::Parent>(|change, world| {
+            let parent = change.0.get::<base_components::Parent>().unwrap().entity;
+            if world.is_alive(parent) {
+                Ok(change)
+            } else {
+                Err(world::WorldError::EntityNotFound)
+            }
+        })
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(AdoptSystem {})
+        .build();
+    match world.try_execute_stage(&stage1) {
+        Err(world::WorldError::ChangesRejected(errors)) => assert!(matches!(
+            errors.as_slice(),
+            [world::WorldError::EntityNotFound]
+        )),
+        x => panic!("expected a rejected command, got {:?}", x),
+    }
+    assert!(world.get_component::<base_components::Parent>(id).is_none());
+}
+
+//spawns a named orphan of a parent that doesn't exist for every positioned entity
+struct SpawnOrphanSystem {}
+impl system::System for SpawnOrphanSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        query_result
+            .add_entity()
+            .with(base_components::Name {
+                name: "orphan".to_string(),
+            })
+            .with(base_components::Parent {
+                entity: entity_id::EntityId::from_parts(1000, 0),
+            })
+            .spawn();
+    }
+}
+
+#[test]
+fn rejected_system_spawns_are_undone() {
+    let mut world = default_world::DefaultWorld::get()
+        .with_typed_validator::<base_components::Parent>(|change, world| {
+            let parent = change.0.get::<base_components::Parent>().unwrap().entity;
+            if world.is_alive(parent) {
+                Ok(change)
+            } else {
+                Err(world::WorldError::EntityNotFound)
+            }
+        })
+        .build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(SpawnOrphanSystem {})
+        .build();
+    assert!(world.try_execute_stage(&stage1).is_err());
+    //no half spawned entity is left behind and its id is free again
+    assert_eq!(world.number_of_entities(), 1);
+    let next = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    assert_eq!(next.index(), 1);
+}
+
+#[test]
+fn rejected_spawns_roll_back_their_cascade() {
+    //children named "locked" can't be given a parent
+    let mut world = default_world::DefaultWorld::get()
+        .with_typed_validator::<base_components::Parent>(|change, world| match world
+            .get_component::<base_components::Name>(
+            change.0.entity_id(),
+        ) {
+            Some(name) if name.name == "locked" => {
+                Err(world::WorldError::InvalidChange("locked".to_string()))
+            }
+            _ => Ok(change),
+        })
+        .build();
+    let child = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "child".to_string(),
+        })
+        .spawn();
+    let locked = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "locked".to_string(),
+        })
+        .spawn();
+    let parent = world
+        .add_entity()
+        .with(base_components::Children {
+            entities: vec![child],
+        })
+        .spawn();
+    let rejected = world
+        .add_entity()
+        .with(base_components::Children {
+            entities: vec![child, locked],
+        })
+        .try_spawn();
+    assert!(matches!(rejected, Err(world::WorldError::InvalidChange(_))));
+    //the child keeps its old parent instead of losing it to the rollback
+    assert_eq!(
+        world
+            .get_component::<base_components::Parent>(child)
+            .unwrap()
+            .entity,
+        parent
+    );
+    assert_eq!(
+        world
+            .get_component::<base_components::Children>(parent)
+            .unwrap()
+            .entities,
+        vec![child]
+    );
+    assert!(world
+        .get_component::<base_components::Parent>(locked)
+        .is_none());
+    assert_eq!(world.number_of_entities(), 3);
+}
+
+fn ping(
+    change: &query::Change,
+    _: &world::World,
+    _: &mut resource_writer::ResourceWriter,
+) -> Vec<query::Change> {
+    vec![query::Change::new(
+        component::UntypedComponent::new(
+            base_components::Position { x: 0, y: 0 },
+            change.0.entity_id(),
+        ),
+        query::ChangeType::UpdateComponent,
+    )]
+}
+
+fn pong(
+    change: &query::Change,
+    _: &world::World,
+    _: &mut resource_writer::ResourceWriter,
+) -> Vec<query::Change> {
+    vec![query::Change::new(
+        component::UntypedComponent::new(
+            base_components::Name {
+                name: "pong".to_string(),
+            },
+            change.0.entity_id(),
+        ),
+        query::ChangeType::UpdateComponent,
+    )]
+}
+
+#[test]
+fn hook_cycles_hit_the_depth_limit() {
+    let mut world = world::WorldBuilder::new()
+        .with_typed_hook::<base_components::Name>(ping)
+        .with_typed_hook::<base_components::Position>(pong)
+        .with_change_depth_limit(10)
+        .build();
+    let result = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "ping".to_string(),
+        })
+        .try_spawn();
+    match result {
+        Err(world::WorldError::ChangeDepthExceeded(hooks)) => {
+            assert_eq!(hooks.len(), 11);
+            assert!(hooks[0].ends_with("ping"));
+            assert!(hooks[1].ends_with("pong"));
+        }
+        _ => panic!("expected the depth limit to be exceeded"),
+    }
+}
+
+#[test]
+fn changes_carry_previous_values() {
+    let moves = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let recorded = moves.clone();
+    let mut world = world::WorldBuilder::new()
+        .with_typed_hook::<base_components::Position>(move |change, _, _| {
+            if let Some((old, new)) = change.update::<base_components::Position>() {
+                recorded.lock().push((old.x, new.x));
+            }
+            if change.1 == query::ChangeType::RemoveComponent {
+                let removed = change.previous::<base_components::Position>().unwrap();
+                recorded.lock().push((removed.x, -1));
+            }
+            Vec::new()
+        })
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 0 })
+        .spawn();
+    world
+        .insert_component(id, base_components::Position { x: 2, y: 0 })
+        .unwrap();
+    world
+        .insert_component(id, base_components::Position { x: 3, y: 0 })
+        .unwrap();
+    world.remove_entity(id);
+    assert_eq!(*moves.lock(), vec![(1, 2), (2, 3), (3, -1)]);
+}
@@ -0,0 +1,60 @@
+use melon::*;
+
+struct MoveSystem {}
+impl system::System for MoveSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x += 1;
+        }
+    }
+}
+
+#[test]
+fn subscribers_receive_applied_changes() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let everything = world.subscribe(observer::ChangeFilter::new());
+    let positions = world.subscribe(observer::ChangeFilter::new().with::<base_components::Position>());
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "observed".to_string(),
+        })
+        .spawn();
+    //nothing is sent until the changes are flushed
+    assert!(positions.try_recv().is_err());
+    world.flush_changes();
+    assert_eq!(everything.try_iter().count(), 2);
+    let added = positions.try_iter().collect::<Vec<_>>();
+    assert_eq!(added.len(), 1);
+    assert_eq!(added[0].change_type, query::ChangeType::AddComponent);
+    assert_eq!(added[0].component.entity_id(), id);
+    let stage1 = stage::StageBuilder::new()
+        .with_system(MoveSystem {})
+        .build();
+    world.execute_stage(&stage1);
+    let updated = positions.try_iter().collect::<Vec<_>>();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated[0].change_type, query::ChangeType::UpdateComponent);
+    assert_eq!(
+        updated[0]
+            .component
+            .get::<base_components::Position>()
+            .unwrap()
+            .x,
+        1
+    );
+    let previous = updated[0].previous.as_ref().unwrap();
+    assert_eq!(previous.get::<base_components::Position>().unwrap().x, 0);
+    drop(everything);
+    world.remove_entity(id);
+    world.flush_changes();
+    let removed = positions.try_iter().collect::<Vec<_>>();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].change_type, query::ChangeType::RemoveComponent);
+}
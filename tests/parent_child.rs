@@ -47,6 +47,6 @@ fn parent() {
             .get_component::<base_components::Parent>(child).is_none(),
         true
     );
-    //one entity should be left
-    assert_eq!(world.number_of_entities(), 1);
+    //the child and the first parent should be left
+    assert_eq!(world.number_of_entities(), 2);
 }
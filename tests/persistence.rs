@@ -0,0 +1,364 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use hashbrown::HashMap;
+use melon::*;
+
+struct MemoryLoader {
+    stored: HashMap<entity_id::EntityId, Vec<component::UntypedComponent>>,
+}
+
+impl hook::Loader for MemoryLoader {
+    fn load(
+        &self,
+        ids: Vec<entity_id::EntityId>,
+        _world: &world::World,
+    ) -> Result<Vec<component::UntypedComponent>, world::WorldError> {
+        Ok(ids
+            .iter()
+            .filter_map(|x| self.stored.get(x))
+            .flatten()
+            .cloned()
+            .collect())
+    }
+}
+
+fn stored_entity(id: u64) -> (entity_id::EntityId, Vec<component::UntypedComponent>) {
+    let id = entity_id::EntityId::new_with_number(id);
+    (
+        id,
+        vec![
+            component::UntypedComponent::new(base_components::Position { x: 1, y: 2 }, id),
+            component::UntypedComponent::new(
+                base_components::Name {
+                    name: "stored".to_string(),
+                },
+                id,
+            ),
+        ],
+    )
+}
+
+#[test]
+fn load() {
+    let (id, components) = stored_entity(1);
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(MemoryLoader {
+            stored: [(id, components)].into_iter().collect(),
+        })
+        .build();
+    assert_eq!(world.load(vec![id]).unwrap(), vec![id]);
+    assert_eq!(world.number_of_entities(), 1);
+    assert_eq!(
+        world
+            .get_component::<base_components::Position>(id)
+            .unwrap()
+            .y,
+        2
+    );
+    //already loaded entities are skipped
+    assert!(world.load(vec![id]).unwrap().is_empty());
+}
+
+#[test]
+fn load_unknown_entity() {
+    let (id, components) = stored_entity(1);
+    let unknown = entity_id::EntityId::new_with_number(2);
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(MemoryLoader {
+            stored: [(id, components)].into_iter().collect(),
+        })
+        .build();
+    match world.load(vec![id, unknown]) {
+        Err(world::WorldError::EntitiesNotFound(missing)) => assert_eq!(missing, vec![unknown]),
+        _ => panic!("expected unknown entity to be reported"),
+    }
+    assert_eq!(world.number_of_entities(), 0);
+}
+
+#[test]
+fn load_without_loader() {
+    let mut world = world::WorldBuilder::new().build();
+    assert!(matches!(
+        world.load(vec![entity_id::EntityId::new_with_number(1)]),
+        Err(world::WorldError::LoaderNotFound)
+    ));
+}
+
+#[derive(Clone, Default)]
+struct MemoryStore {
+    stored: Arc<Mutex<HashMap<entity_id::EntityId, Vec<component::UntypedComponent>>>>,
+}
+
+impl hook::Unloader for MemoryStore {
+    fn hook(
+        &self,
+        components: Vec<component::UntypedComponent>,
+        _world: &world::World,
+    ) -> Result<(), world::WorldError> {
+        let mut stored = self.stored.lock().unwrap();
+        for component in components {
+            stored
+                .entry(component.entity_id())
+                .or_default()
+                .push(component);
+        }
+        Ok(())
+    }
+}
+
+impl hook::Loader for MemoryStore {
+    fn load(
+        &self,
+        ids: Vec<entity_id::EntityId>,
+        _world: &world::World,
+    ) -> Result<Vec<component::UntypedComponent>, world::WorldError> {
+        let mut stored = self.stored.lock().unwrap();
+        Ok(ids
+            .iter()
+            .filter_map(|x| stored.remove(x))
+            .flatten()
+            .collect())
+    }
+}
+
+static UNLOADED: AtomicUsize = AtomicUsize::new(0);
+static REMOVED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_removals(
+    change: &query::Change,
+    _: &world::World,
+    _: &mut resource_writer::ResourceWriter,
+) -> Vec<query::Change> {
+    match change.1 {
+        query::ChangeType::UnloadComponent => UNLOADED.fetch_add(1, Ordering::SeqCst),
+        query::ChangeType::RemoveComponent => REMOVED.fetch_add(1, Ordering::SeqCst),
+        _ => 0,
+    };
+    Vec::new()
+}
+
+#[test]
+fn unload_and_reload() {
+    let store = MemoryStore::default();
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(store.clone())
+        .with_unloader(store.clone())
+        .with_typed_hook::<base_components::Name>(count_removals)
+        .build();
+    let child = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "child".to_string(),
+        })
+        .spawn();
+    let parent = world
+        .add_entity()
+        .with(base_components::Position { x: 3, y: 4 })
+        .with(base_components::Children {
+            entities: vec![child],
+        })
+        .spawn();
+    assert_eq!(
+        world.unload(vec![child, parent]).unwrap(),
+        vec![child, parent]
+    );
+    assert_eq!(world.number_of_entities(), 0);
+    assert_eq!(store.stored.lock().unwrap().len(), 2);
+    assert_eq!(UNLOADED.load(Ordering::SeqCst), 1);
+    assert_eq!(REMOVED.load(Ordering::SeqCst), 0);
+
+    world.load(vec![parent, child]).unwrap();
+    assert_eq!(world.number_of_entities(), 2);
+    assert_eq!(
+        world
+            .get_component::<base_components::Parent>(child)
+            .unwrap()
+            .entity,
+        parent
+    );
+    assert!(matches!(
+        world.unload(vec![entity_id::EntityId::new_with_number(7)]),
+        Err(world::WorldError::EntitiesNotFound(_))
+    ));
+}
+
+#[test]
+fn load_parent_without_children() {
+    let store = MemoryStore::default();
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(store.clone())
+        .with_unloader(store.clone())
+        .build();
+    let child = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "child".to_string(),
+        })
+        .spawn();
+    let parent = world
+        .add_entity()
+        .with(base_components::Children {
+            entities: vec![child],
+        })
+        .spawn();
+    world.unload(vec![child, parent]).unwrap();
+    world.load(vec![parent]).unwrap();
+    assert_eq!(world.number_of_entities(), 1);
+    assert!(!world.contains_entity(child));
+    assert_eq!(world.load(vec![child]).unwrap(), vec![child]);
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(child)
+            .unwrap()
+            .name,
+        "child"
+    );
+    assert_eq!(
+        world
+            .get_component::<base_components::Parent>(child)
+            .unwrap()
+            .entity,
+        parent
+    );
+}
+
+#[test]
+fn sled_store() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let store = sled_store::SledStore::new(&db).unwrap();
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(store.clone())
+        .with_unloader(store.clone())
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 5, y: 6 })
+        .with(base_components::Name {
+            name: "stored".to_string(),
+        })
+        .spawn();
+    world.unload(vec![id]).unwrap();
+    assert_eq!(world.number_of_entities(), 0);
+    assert_eq!(store.fetch(id, world.registry()).unwrap().len(), 2);
+
+    world.load(vec![id]).unwrap();
+    assert_eq!(
+        world
+            .get_component::<base_components::Position>(id)
+            .unwrap()
+            .x,
+        5
+    );
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(id)
+            .unwrap()
+            .name,
+        "stored"
+    );
+    //the loaded entity is only kept in memory, so despawning it is final
+    assert!(store.fetch(id, world.registry()).unwrap().is_empty());
+    world.remove_entity(id);
+    assert!(world.load(vec![id]).is_err());
+    assert_eq!(world.number_of_entities(), 0);
+}
+
+#[test]
+fn sled_store_keeps_ids_across_restarts() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let store = sled_store::SledStore::new(&db).unwrap();
+    let builder = || {
+        default_world::DefaultWorld::get()
+            .with_loader(store.clone())
+            .with_unloader(store.clone())
+    };
+    let mut world = builder().build();
+    let stored = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 2 })
+        .spawn();
+    world.unload(vec![stored]).unwrap();
+
+    //a restarted world that restores the allocator doesn't reissue the stored id
+    let mut world = builder()
+        .with_allocator(store.allocator().unwrap().unwrap())
+        .build();
+    let fresh = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    assert_ne!(fresh.index(), stored.index());
+    assert_eq!(world.load(vec![stored]).unwrap(), vec![stored]);
+    assert_eq!(world.number_of_entities(), 2);
+
+    //one that doesn't reports the collision instead of skipping the stored entity
+    let mut world = builder().build();
+    let fresh = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    world.remove_entity(fresh);
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    assert!(matches!(
+        world.load(vec![stored]),
+        Err(world::WorldError::EntityIdConflict(_))
+    ));
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Unregistered {}
+impl component::ComponentType for Unregistered {}
+
+#[test]
+fn failed_unload_keeps_entities() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let store = sled_store::SledStore::new(&db).unwrap();
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(store.clone())
+        .with_unloader(store.clone())
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 5, y: 6 })
+        .with(Unregistered {})
+        .spawn();
+    assert!(matches!(
+        world.unload(vec![id]),
+        Err(world::WorldError::ComponentNotRegistered(_))
+    ));
+    assert_eq!(world.number_of_entities(), 1);
+    assert!(world.get_component::<Unregistered>(id).is_some());
+    assert!(store.fetch(id, world.registry()).unwrap().is_empty());
+}
+
+#[test]
+fn failed_load_is_reported() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let store = sled_store::SledStore::new(&db).unwrap();
+    let mut world = default_world::DefaultWorld::get()
+        .with_component::<Unregistered>()
+        .with_unloader(store.clone())
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 5, y: 6 })
+        .with(Unregistered {})
+        .spawn();
+    world.unload(vec![id]).unwrap();
+
+    //a world that doesn't know a stored component can't load the entity
+    let mut world = default_world::DefaultWorld::get()
+        .with_loader(store.clone())
+        .build();
+    assert!(matches!(
+        world.load(vec![id]),
+        Err(world::WorldError::ComponentNotRegistered(_))
+    ));
+    assert_eq!(world.number_of_entities(), 0);
+}
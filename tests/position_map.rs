@@ -0,0 +1,43 @@
+use melon::*;
+
+fn nearest(world: &world::World, position: [i32; 2]) -> Vec<(entity_id::EntityId, [i32; 2])> {
+    world
+        .read_resource(|x: &position_map::PositionMap| x.get_nearest(position, 10))
+        .unwrap()
+}
+
+struct MoveSystem {}
+impl system::System for MoveSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x += 10;
+        }
+    }
+}
+
+#[test]
+fn position_map_tracks_entities() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let a = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 2 })
+        .spawn();
+    assert_eq!(nearest(&world, [0, 0]), vec![(a, [1, 2])]);
+    let b = world
+        .add_entity()
+        .with(base_components::Position { x: 5, y: 5 })
+        .spawn();
+    assert_eq!(nearest(&world, [6, 6]), vec![(b, [5, 5]), (a, [1, 2])]);
+    let stage1 = stage::StageBuilder::new()
+        .with_system(MoveSystem {})
+        .build();
+    world.execute_stage(&stage1);
+    assert_eq!(nearest(&world, [0, 0]), vec![(a, [11, 2]), (b, [15, 5])]);
+    world.remove_entity(a);
+    assert_eq!(nearest(&world, [0, 0]), vec![(b, [15, 5])]);
+}
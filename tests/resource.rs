@@ -0,0 +1,66 @@
+use melon::*;
+
+#[derive(Debug, PartialEq)]
+struct Score(u32);
+impl resource::Resource for Score {}
+
+//moves the score from one resource to another through deferred writes
+struct BankSystem {}
+impl system::System for BankSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        query_result.write_resource(|x: &mut Score| x.0 += 1);
+        query_result.take_resource(|_: Score| {});
+        query_result.insert_resource(Score(100));
+    }
+}
+
+#[test]
+fn insert_and_remove_resources() {
+    let mut world = default_world::DefaultWorld::get().build();
+    assert!(world.insert_resource(Score(1)).is_none());
+    assert_eq!(world.insert_resource(Score(2)), Some(Score(1)));
+    assert_eq!(world.read_resource(|x: &Score| x.0).unwrap(), 2);
+    assert_eq!(world.take_resource::<Score>().unwrap(), Score(2));
+    assert!(matches!(
+        world.remove_resource::<Score>(),
+        Err(world::WorldError::ResourceNotFound)
+    ));
+    world.insert_resource(Score(3));
+    world.remove_resource::<Score>().unwrap();
+    assert!(world.read_resource(|x: &Score| x.0).is_err());
+}
+
+#[test]
+fn deferred_resource_writes_report_missing_resources() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(BankSystem {})
+        .build();
+    //the write and the take fail, the insert still happens
+    match world.try_execute_stage(&stage1) {
+        Err(world::WorldError::ChangesRejected(errors)) => {
+            assert_eq!(errors.len(), 2);
+            assert!(errors
+                .iter()
+                .all(|x| matches!(x, world::WorldError::ResourceNotFound)));
+        }
+        x => panic!("expected rejected writes, got {:?}", x),
+    }
+    assert_eq!(world.read_resource(|x: &Score| x.0).unwrap(), 100);
+    //the write now bumps the inserted score before the take and insert replace it
+    world.try_execute_stage(&stage1).unwrap();
+    assert_eq!(world.read_resource(|x: &Score| x.0).unwrap(), 100);
+    let mut writer = resource_writer::ResourceWriter::new();
+    writer.remove_resource::<Score>();
+    writer.remove_resource::<Score>();
+    assert!(world.execute_command(writer).is_err());
+}
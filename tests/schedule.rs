@@ -0,0 +1,119 @@
+use melon::schedule::IntoSystemDescriptor;
+use melon::*;
+
+struct Paused(bool);
+impl resource::Resource for Paused {}
+
+//applies x = x * mul + add to every position
+struct AffineSystem {
+    mul: i32,
+    add: i32,
+}
+impl system::System for AffineSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            let mut position = e.get::<base_components::Position>().unwrap();
+            position.x = position.x * self.mul + self.add;
+        }
+    }
+}
+
+fn setup() -> (world::World, entity_id::EntityId) {
+    let mut world = default_world::DefaultWorld::get()
+        .with_resource(Paused(false))
+        .build();
+    let x = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    (world, x)
+}
+
+fn x(world: &world::World, id: entity_id::EntityId) -> i32 {
+    world
+        .get_component::<base_components::Position>(id)
+        .unwrap()
+        .x
+}
+
+#[test]
+fn stages_run_in_order() {
+    let (mut world, id) = setup();
+    let schedule = schedule::ScheduleBuilder::new()
+        .with_stage_after(schedule::UPDATE, "late")
+        .with_system("late", AffineSystem { mul: 10, add: 0 })
+        .with_system(schedule::POST_UPDATE, AffineSystem { mul: 1, add: 3 })
+        .with_system(schedule::UPDATE, AffineSystem { mul: 1, add: 1 })
+        .with_system(schedule::PRE_UPDATE, AffineSystem { mul: 2, add: 0 })
+        .with_startup_system(AffineSystem { mul: 1, add: 5 })
+        .build();
+    assert_eq!(
+        schedule.stage_names().collect::<Vec<_>>(),
+        vec!["pre_update", "update", "late", "post_update"]
+    );
+    world.run_schedule(&schedule);
+    assert_eq!(x(&world, id), 113);
+    //startup systems only run once
+    world.run_schedule(&schedule);
+    assert_eq!(x(&world, id), 2273);
+}
+
+#[test]
+fn systems_run_after_their_dependencies() {
+    let (mut world, id) = setup();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(AffineSystem { mul: 2, add: 0 }.after("add"))
+        .with_system(AffineSystem { mul: 1, add: 1 }.label("add"))
+        .build();
+    assert_eq!(stage1.batches(), &[vec![1], vec![0]]);
+    world.execute_stage(&stage1);
+    assert_eq!(x(&world, id), 2);
+    let stage2 = stage::StageBuilder::new()
+        .with_system(AffineSystem { mul: 1, add: 1 })
+        .with_system(AffineSystem { mul: 3, add: 0 }.before("first"))
+        .with_system(AffineSystem { mul: 1, add: 2 }.label("first"))
+        .try_build();
+    assert!(matches!(
+        stage2,
+        Err(world::WorldError::SystemConflict(0, 1, _))
+    ));
+    let cyclic = stage::StageBuilder::new()
+        .with_system(AffineSystem { mul: 1, add: 1 }.label("a").after("b"))
+        .with_system(AffineSystem { mul: 1, add: 1 }.label("b").after("a"))
+        .with_system(AffineSystem { mul: 1, add: 1 })
+        .try_build();
+    assert!(matches!(
+        cyclic,
+        Err(world::WorldError::SystemOrderCycle(systems)) if systems == vec![0, 1]
+    ));
+}
+
+#[test]
+fn run_criteria() {
+    let (mut world, id) = setup();
+    let schedule = schedule::ScheduleBuilder::new()
+        .with_system(
+            schedule::UPDATE,
+            AffineSystem { mul: 1, add: 1 }.run_if(schedule::RunCriteria::every(3)),
+        )
+        .with_system(
+            schedule::POST_UPDATE,
+            AffineSystem { mul: 1, add: 100 }
+                .run_if(schedule::RunCriteria::resource(|x: &Paused| !x.0)),
+        )
+        .build();
+    for _ in 0..4 {
+        world.run_schedule(&schedule);
+    }
+    assert_eq!(x(&world, id), 402);
+    world.write_resource(|x: &mut Paused| x.0 = true).unwrap();
+    for _ in 0..3 {
+        world.run_schedule(&schedule);
+    }
+    assert_eq!(x(&world, id), 403);
+}
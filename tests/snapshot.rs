@@ -0,0 +1,81 @@
+use melon::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Score {
+    points: u32,
+}
+impl resource::Resource for Score {}
+
+fn builder() -> world::WorldBuilder {
+    default_world::DefaultWorld::get().with_serializable_resource(Score { points: 0 })
+}
+
+fn round_trip(format: snapshot::SnapshotFormat) {
+    let mut world = builder().build();
+    let child = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "child".to_string(),
+        })
+        .with(base_components::Position { x: 1, y: 1 })
+        .spawn();
+    let parent = world
+        .add_entity()
+        .with(base_components::Position { x: 10, y: -4 })
+        .with(base_components::Children {
+            entities: vec![child],
+        })
+        .spawn();
+    world
+        .write_resource(|score: &mut Score| score.points = 42)
+        .unwrap();
+    let mut buffer = Vec::new();
+    world.save_snapshot(&mut buffer, format).unwrap();
+
+    let restored = builder().from_snapshot(buffer.as_slice(), format).unwrap();
+    assert_eq!(restored.number_of_entities(), 2);
+    assert_eq!(
+        restored
+            .get_component::<base_components::Position>(parent)
+            .unwrap()
+            .y,
+        -4
+    );
+    assert_eq!(
+        restored
+            .get_component::<base_components::Parent>(child)
+            .unwrap()
+            .entity,
+        parent
+    );
+    assert_eq!(
+        restored
+            .read_resource(|score: &Score| score.points)
+            .unwrap(),
+        42
+    );
+}
+
+#[test]
+fn cbor_snapshot() {
+    round_trip(snapshot::SnapshotFormat::Cbor);
+}
+
+#[test]
+fn json_snapshot() {
+    round_trip(snapshot::SnapshotFormat::Json);
+}
+
+#[test]
+fn snapshot_unregistered_component() {
+    let mut world = world::WorldBuilder::new().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    assert!(matches!(
+        world.save_snapshot(Vec::new(), snapshot::SnapshotFormat::Json),
+        Err(world::WorldError::ComponentNotRegistered(_))
+    ));
+}
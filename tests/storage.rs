@@ -0,0 +1,147 @@
+use melon::*;
+
+struct MoveSystem {}
+impl system::System for MoveSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .with::<base_components::Name>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x += 1;
+        }
+    }
+}
+
+fn storage(mode: storage::StorageMode) {
+    let mut world = default_world::DefaultWorld::get()
+        .with_storage(mode)
+        .build();
+    let named = (0..10)
+        .map(|i| {
+            world
+                .add_entity()
+                .with(base_components::Position { x: i, y: 0 })
+                .with(base_components::Name {
+                    name: i.to_string(),
+                })
+                .spawn()
+        })
+        .collect::<Vec<_>>();
+    let unnamed = world
+        .add_entity()
+        .with(base_components::Position { x: 100, y: 0 })
+        .spawn();
+    let stage = stage::StageBuilder::new()
+        .with_system(MoveSystem {})
+        .build();
+    world.execute_stage(&stage);
+    for (i, id) in named.iter().enumerate() {
+        assert_eq!(
+            world
+                .get_component::<base_components::Position>(*id)
+                .unwrap()
+                .x,
+            i as i32 + 1
+        );
+    }
+    assert_eq!(
+        world
+            .get_component::<base_components::Position>(unnamed)
+            .unwrap()
+            .x,
+        100
+    );
+    world.remove_entity(named[0]);
+    assert_eq!(world.number_of_entities(), 10);
+    assert!(world
+        .get_component::<base_components::Name>(named[0])
+        .is_none());
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(named[9])
+            .unwrap()
+            .name,
+        "9"
+    );
+    assert_eq!(
+        world
+            .query_world(
+                query::QueryBuilder::new()
+                    .with::<base_components::Name>()
+                    .build()
+            )
+            .iter()
+            .count(),
+        9
+    );
+}
+
+#[test]
+fn sparse_storage() {
+    storage(storage::StorageMode::Sparse);
+}
+
+#[test]
+fn archetype_storage() {
+    storage(storage::StorageMode::Archetype);
+}
+
+//a hook gives named entities a player component while they are being spawned
+fn spawn_with_hook(mode: storage::StorageMode) {
+    let mut world = default_world::DefaultWorld::get()
+        .with_storage(mode)
+        .with_typed_hook::<base_components::Name>(|change, _, _| {
+            let name = change.get::<base_components::Name>().unwrap();
+            vec![query::Change::new(
+                component::UntypedComponent::new(
+                    base_components::Player {
+                        player_name: name.name.clone(),
+                        player_id: 0,
+                    },
+                    change.0.entity_id(),
+                ),
+                query::ChangeType::AddComponent,
+            )]
+        })
+        .build();
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 0 })
+        .with(base_components::Name {
+            name: "player".to_string(),
+        })
+        .spawn();
+    assert_eq!(world.get_all_components_of_entity(id).unwrap().len(), 3);
+    assert_eq!(
+        world
+            .get_component::<base_components::Player>(id)
+            .unwrap()
+            .player_name,
+        "player"
+    );
+    assert_eq!(
+        world
+            .query_world(
+                query::QueryBuilder::new()
+                    .with::<base_components::Position>()
+                    .with::<base_components::Player>()
+                    .build()
+            )
+            .iter()
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn sparse_storage_spawn_with_hook() {
+    spawn_with_hook(storage::StorageMode::Sparse);
+}
+
+#[test]
+fn archetype_storage_spawn_with_hook() {
+    spawn_with_hook(storage::StorageMode::Archetype);
+}
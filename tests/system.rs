@@ -39,3 +39,728 @@ fn system() {
     world.execute_stage(&stage1);
     assert_eq!(world.get_component::<base_components::Position>(x).unwrap().x, 1);
 }
+
+struct SpawnSystem {}
+impl system::System for SpawnSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        let x = rand::Rng::gen_range(query_result.rng(), 0..1000);
+        query_result
+            .add_entity()
+            .with(base_components::Position { x, y: 0 })
+            .spawn();
+    }
+}
+
+fn seeded_run(seed: u64) -> Vec<(entity_id::EntityId, i32)> {
+    let mut world = default_world::DefaultWorld::get().with_seed(seed).build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(SpawnSystem {})
+        .with_system(SpawnSystem {})
+        .build();
+    for _ in 0..3 {
+        world.execute_stage(&stage1);
+    }
+    let mut positions = world
+        .query_world(
+            query::QueryBuilder::new()
+                .with::<base_components::Position>()
+                .build(),
+        )
+        .iter()
+        .map(|e| (e.get_id(), e.get::<base_components::Position>().unwrap().x))
+        .collect::<Vec<_>>();
+    positions.sort_by_key(|(id, _)| id.id());
+    positions
+}
+
+#[test]
+fn seeded_runs_are_reproducible() {
+    let first = seeded_run(7);
+    assert_eq!(first.len(), 7);
+    assert_eq!(first, seeded_run(7));
+}
+
+struct TagSystem {
+    target: entity_id::EntityId,
+}
+impl system::System for TagSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            if e.get::<base_components::Position>().unwrap().x > 0 {
+                e.remove::<base_components::Position>();
+            } else {
+                e.insert(base_components::Name {
+                    name: "origin".to_string(),
+                });
+            }
+        }
+        query_result.insert_component(
+            self.target,
+            base_components::Name {
+                name: "target".to_string(),
+            },
+        );
+    }
+}
+
+#[test]
+fn system_inserts_and_removes_components() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let origin = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let moved = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 0 })
+        .with(base_components::Player {
+            player_name: "p".to_string(),
+            player_id: 1,
+        })
+        .spawn();
+    let target = world
+        .add_entity()
+        .with(base_components::Player {
+            player_name: "t".to_string(),
+            player_id: 2,
+        })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(TagSystem { target })
+        .build();
+    world.execute_stage(&stage1);
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(origin)
+            .unwrap()
+            .name,
+        "origin"
+    );
+    assert!(world
+        .get_component::<base_components::Position>(moved)
+        .is_none());
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(target)
+            .unwrap()
+            .name,
+        "target"
+    );
+}
+
+struct KillSystem {}
+impl system::System for KillSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        let dead = query_result
+            .iter()
+            .filter_map(|e| {
+                let dead = e.get::<base_components::Position>().unwrap().x < 0;
+                dead.then(|| e.get_id())
+            })
+            .collect::<Vec<_>>();
+        for id in dead {
+            query_result.despawn_recursive(id);
+        }
+    }
+}
+
+#[test]
+fn system_despawns_entities() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let grandchild = world
+        .add_entity()
+        .with(base_components::Name {
+            name: "grandchild".to_string(),
+        })
+        .spawn();
+    let child = world
+        .add_entity()
+        .with(base_components::Children {
+            entities: vec![grandchild],
+        })
+        .spawn();
+    world
+        .add_entity()
+        .with(base_components::Position { x: -1, y: 0 })
+        .with(base_components::Children {
+            entities: vec![child],
+        })
+        .spawn();
+    let survivor = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(KillSystem {})
+        .build();
+    world.execute_stage(&stage1);
+    assert_eq!(world.number_of_entities(), 1);
+    assert!(world.is_alive(survivor));
+    assert!(!world.is_alive(grandchild));
+}
+
+//records how many entities each run of the system matched
+struct CountSystem {
+    query: fn() -> query::Query,
+    counts: std::sync::Arc<parking_lot::Mutex<Vec<usize>>>,
+}
+impl system::System for CountSystem {
+    fn query(&self) -> query::Query {
+        (self.query)()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        self.counts.lock().push(query_result.iter().count());
+    }
+}
+
+fn count_runs(world: &mut world::World, query: fn() -> query::Query, runs: usize) -> Vec<usize> {
+    let counts = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let stage1 = stage::StageBuilder::new()
+        .with_system(TestSystem {})
+        .with_system(CountSystem {
+            query,
+            counts: counts.clone(),
+        })
+        .build();
+    for _ in 0..runs {
+        world.execute_stage(&stage1);
+    }
+    let counts = counts.lock().clone();
+    counts
+}
+
+#[test]
+fn query_filters() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "named".to_string(),
+        })
+        .spawn();
+    let mut without = world.query_world(
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .without::<base_components::Name>()
+            .build(),
+    );
+    assert_eq!(without.iter().count(), 1);
+    let mut optional = world.query_world(
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .optional::<base_components::Name>()
+            .build(),
+    );
+    assert_eq!(
+        optional
+            .iter()
+            .filter_map(|e| e.get::<base_components::Name>().map(|x| x.name.clone()))
+            .collect::<Vec<_>>(),
+        vec!["named".to_string()]
+    );
+}
+
+#[test]
+fn change_filters() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let named = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "named".to_string(),
+        })
+        .spawn();
+    //positions are updated by TestSystem every run, names never
+    let changed = count_runs(
+        &mut world,
+        || {
+            query::QueryBuilder::new()
+                .changed::<base_components::Position>()
+                .build()
+        },
+        3,
+    );
+    assert_eq!(changed, vec![1, 1, 1]);
+    let added = count_runs(
+        &mut world,
+        || {
+            query::QueryBuilder::new()
+                .added::<base_components::Name>()
+                .build()
+        },
+        2,
+    );
+    assert_eq!(added, vec![1, 0]);
+    world
+        .insert_component(named, base_components::Name {
+            name: "renamed".to_string(),
+        })
+        .unwrap();
+    let changed = count_runs(
+        &mut world,
+        || {
+            query::QueryBuilder::new()
+                .changed::<base_components::Name>()
+                .build()
+        },
+        2,
+    );
+    assert_eq!(changed, vec![1, 0]);
+}
+
+//spawns a named entity every run
+struct SpawnNamedSystem {}
+impl system::System for SpawnNamedSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        query_result
+            .add_entity()
+            .with(base_components::Name {
+                name: "spawned".to_string(),
+            })
+            .spawn();
+    }
+}
+
+#[test]
+fn change_filters_see_changes_of_the_same_batch() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let counts = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let stage1 = stage::StageBuilder::new()
+        .with_system(SpawnNamedSystem {})
+        .with_system(CountSystem {
+            query: || {
+                query::QueryBuilder::new()
+                    .added::<base_components::Name>()
+                    .build()
+            },
+            counts: counts.clone(),
+        })
+        .build();
+    assert_eq!(stage1.batches().len(), 1);
+    for _ in 0..3 {
+        world.execute_stage(&stage1);
+    }
+    //each run sees the entity spawned by the previous one
+    assert_eq!(*counts.lock(), vec![0, 1, 1]);
+}
+
+//records how many of the names it reads were added since its last run
+struct AddedNamesSystem {
+    counts: std::sync::Arc<parking_lot::Mutex<Vec<usize>>>,
+}
+impl system::System for AddedNamesSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Name>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        let added = query_result
+            .iter()
+            .filter(|x| x.is_added::<base_components::Name>())
+            .count();
+        self.counts.lock().push(added);
+    }
+}
+
+#[test]
+fn component_group_ticks_see_changes_of_the_same_batch() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let counts = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let stage1 = stage::StageBuilder::new()
+        .with_system(SpawnNamedSystem {})
+        .with_system(AddedNamesSystem {
+            counts: counts.clone(),
+        })
+        .build();
+    assert_eq!(stage1.batches().len(), 1);
+    for _ in 0..3 {
+        world.execute_stage(&stage1);
+    }
+    assert_eq!(*counts.lock(), vec![0, 1, 1]);
+}
+
+struct MoveOddSystem {
+    moved: std::sync::Arc<parking_lot::Mutex<Vec<bool>>>,
+}
+impl system::System for MoveOddSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            self.moved
+                .lock()
+                .push(e.is_changed::<base_components::Position>());
+            let mut position = e.get::<base_components::Position>().unwrap();
+            if position.y % 2 == 1 {
+                position.x += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn component_group_ticks() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 1 })
+        .spawn();
+    let moved = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let stage1 = stage::StageBuilder::new()
+        .with_system(MoveOddSystem {
+            moved: moved.clone(),
+        })
+        .build();
+    world.execute_stage(&stage1);
+    world.execute_stage(&stage1);
+    //the system's own moves don't count as changes on its next run
+    assert_eq!(*moved.lock(), vec![true, false]);
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    moved.lock().clear();
+    world.execute_stage(&stage1);
+    world.execute_stage(&stage1);
+    moved.lock().sort();
+    //the even entity is only changed on the run after it was spawned
+    assert_eq!(*moved.lock(), vec![false, false, false, true]);
+}
+
+struct RenameSystem {
+    query: typed_query::Query<(
+        &'static base_components::Position,
+        &'static mut base_components::Name,
+        Option<&'static base_components::Player>,
+    )>,
+}
+impl system::System for RenameSystem {
+    fn query(&self) -> query::Query {
+        self.query.build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        self.query
+            .for_each(query_result, |_, (position, name, player)| {
+                if let Some(player) = player {
+                    name.name = format!("{}@{}", player.player_name, position.x);
+                }
+            });
+    }
+}
+
+#[test]
+fn typed_query() {
+    let query = typed_query::Query::<(
+        &base_components::Position,
+        &mut base_components::Name,
+        Option<&base_components::Player>,
+    )>::new()
+    .build();
+    assert_eq!(query.components.len(), 2);
+    assert!(query.reads.contains(&component::type_id::<base_components::Position>()));
+    assert!(query.reads.contains(&component::type_id::<base_components::Player>()));
+    assert!(query.writes.contains(&component::type_id::<base_components::Name>()));
+    let mut world = default_world::DefaultWorld::get().build();
+    let player = world
+        .add_entity()
+        .with(base_components::Position { x: 3, y: 0 })
+        .with(base_components::Name {
+            name: "p".to_string(),
+        })
+        .with(base_components::Player {
+            player_name: "one".to_string(),
+            player_id: 1,
+        })
+        .spawn();
+    let npc = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "npc".to_string(),
+        })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(RenameSystem {
+            query: typed_query::Query::new(),
+        })
+        .build();
+    world.execute_stage(&stage1);
+    let name = |id| {
+        world
+            .get_component::<base_components::Name>(id)
+            .unwrap()
+            .name
+            .clone()
+    };
+    assert_eq!(name(player), "one@3");
+    assert_eq!(name(npc), "npc");
+    //the untouched name isn't marked as changed
+    let ticks = world
+        .get_component_ticks::<base_components::Name>(npc)
+        .unwrap();
+    assert_eq!(ticks.changed, ticks.added);
+}
+
+#[test]
+fn query_fetches_only_queried_components() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "named".to_string(),
+        })
+        .spawn();
+    let mut result = world.query_world(
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build(),
+    );
+    for e in result.iter() {
+        assert!(e.get::<base_components::Position>().is_some());
+        assert!(e.get::<base_components::Name>().is_none());
+    }
+    let mut result = world.query_world(
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .fetch_all()
+            .build(),
+    );
+    for e in result.iter() {
+        assert_eq!(e.get::<base_components::Name>().unwrap().name, "named");
+    }
+}
+
+//renames and demotes players without fetching their names or player components
+struct DemoteSystem {}
+impl system::System for DemoteSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.insert(base_components::Name {
+                name: "demoted".to_string(),
+            });
+            assert!(e.remove::<base_components::Player>().is_none());
+        }
+    }
+}
+
+#[test]
+fn unfetched_components_can_be_inserted_and_removed() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let events = world.subscribe(observer::ChangeFilter::new().with::<base_components::Name>());
+    let id = world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .with(base_components::Name {
+            name: "player".to_string(),
+        })
+        .with(base_components::Player {
+            player_name: "one".to_string(),
+            player_id: 1,
+        })
+        .spawn();
+    //skip the spawn's events
+    world.flush_changes();
+    events.try_iter().count();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(DemoteSystem {})
+        .build();
+    world.try_execute_stage(&stage1).unwrap();
+    //the existing name is updated rather than added again
+    let event = events.try_recv().unwrap();
+    assert!(matches!(
+        event.change_type,
+        query::ChangeType::UpdateComponent
+    ));
+    assert_eq!(
+        event
+            .previous
+            .unwrap()
+            .get::<base_components::Name>()
+            .unwrap()
+            .name,
+        "player"
+    );
+    assert_eq!(
+        world
+            .get_component::<base_components::Name>(id)
+            .unwrap()
+            .name,
+        "demoted"
+    );
+    assert!(world.get_component::<base_components::Player>(id).is_none());
+}
+
+struct DoubleSystem {}
+impl system::System for DoubleSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .with::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x *= 2;
+        }
+    }
+}
+
+struct ReadNameSystem {}
+impl system::System for ReadNameSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Name>()
+            .build()
+    }
+    fn execute(&self, _query_result: &mut query::QueryResult, _world: &world::World) {}
+}
+
+#[test]
+fn conflicting_systems_run_in_order() {
+    let mut world = default_world::DefaultWorld::get().build();
+    let x = world
+        .add_entity()
+        .with(base_components::Position { x: 1, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(TestSystem {})
+        .with_system(ReadNameSystem {})
+        .with_system(DoubleSystem {})
+        .with_system(ReadNameSystem {})
+        .build();
+    assert_eq!(stage1.batches(), &[vec![0, 1, 3], vec![2]]);
+    world.execute_stage(&stage1);
+    //neither write is lost
+    assert_eq!(
+        world
+            .get_component::<base_components::Position>(x)
+            .unwrap()
+            .x,
+        4
+    );
+    let conflict = stage::StageBuilder::new()
+        .with_system(ReadNameSystem {})
+        .with_system(TestSystem {})
+        .with_system(DoubleSystem {})
+        .try_build();
+    assert!(matches!(
+        conflict,
+        Err(world::WorldError::SystemConflict(1, 2, components))
+            if components == vec![component::type_id::<base_components::Position>()]
+    ));
+    assert!(stage::StageBuilder::new()
+        .with_system(ReadNameSystem {})
+        .with_system(ReadNameSystem {})
+        .try_build()
+        .is_ok());
+}
+
+//moves positions its query declares as only read
+struct UndeclaredMoveSystem {}
+impl system::System for UndeclaredMoveSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Position>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        for e in query_result.iter() {
+            e.get::<base_components::Position>().unwrap().x += 1;
+        }
+    }
+}
+
+//systems sharing a batch may both read what one of them writes, so undeclared writes are refused
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "which its query only reads")]
+fn writes_to_read_components_are_refused() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Position { x: 0, y: 0 })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(UndeclaredMoveSystem {})
+        .with_system(UndeclaredMoveSystem {})
+        .build();
+    assert_eq!(stage1.batches().len(), 1);
+    world.execute_stage(&stage1);
+}
+
+//renames through a typed query while its query only reads names
+struct UndeclaredRenameSystem {}
+impl system::System for UndeclaredRenameSystem {
+    fn query(&self) -> query::Query {
+        query::QueryBuilder::new()
+            .read::<base_components::Name>()
+            .build()
+    }
+    fn execute(&self, query_result: &mut query::QueryResult, _world: &world::World) {
+        query_result.for_each::<&mut base_components::Name>(|_, name| {
+            name.name = "renamed".to_string();
+        });
+    }
+}
+
+#[test]
+#[should_panic(expected = "doesn't declare as written")]
+fn typed_queries_only_write_declared_components() {
+    let mut world = default_world::DefaultWorld::get().build();
+    world
+        .add_entity()
+        .with(base_components::Name {
+            name: "named".to_string(),
+        })
+        .spawn();
+    let stage1 = stage::StageBuilder::new()
+        .with_system(UndeclaredRenameSystem {})
+        .build();
+    world.execute_stage(&stage1);
+}
@@ -1,4 +1,5 @@
 use melon::base_components::*;
+use melon::component::ComponentType;
 use melon::world::*;
 
 #[test]
@@ -7,3 +8,156 @@ fn entity_builder_test() {
     world.add_entity().with(Position { x: 0, y: 0 }).spawn();
     assert_eq!(world.number_of_entities(), 1);
 }
+
+#[test]
+fn component_registry_round_trip() {
+    let mut world = WorldBuilder::new().with_component::<Position>().build();
+    let id = world.add_entity().with(Position { x: 3, y: 7 }).spawn();
+    let component = world
+        .get_component_by_instance_id(melon::component::ComponentInstanceId::new::<Position>(id))
+        .unwrap()
+        .clone();
+    let registry = world.registry();
+    let json = registry.to_json(&component).unwrap();
+    let from_json = registry.from_json(component.get_type(), json, id).unwrap();
+    assert_eq!(from_json.get::<Position>().unwrap().y, 7);
+    let cbor = registry.to_cbor(&component).unwrap();
+    let from_cbor = registry.from_cbor(component.get_type(), &cbor, id).unwrap();
+    assert_eq!(from_cbor.get::<Position>().unwrap().x, 3);
+    assert_eq!(component.get_type().to_string(), "Position");
+    assert!(matches!(
+        registry.to_json(
+            &Name {
+                name: "unregistered".to_string()
+            }
+            .into_untyped(id)
+        ),
+        Err(WorldError::ComponentNotRegistered(_))
+    ));
+}
+
+#[test]
+fn entity_ids_are_generational() {
+    let mut world = WorldBuilder::new().build();
+    let first = world.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    world.remove_entity(first);
+    assert!(!world.is_alive(first));
+    let second = world.add_entity().with(Position { x: 1, y: 1 }).spawn();
+    //the slot is reused, but the stale id doesn't see the new entity
+    assert_eq!(first.index(), second.index());
+    assert_ne!(first, second);
+    assert!(world.is_alive(second));
+    assert!(world.get_component::<Position>(first).is_none());
+}
+
+#[test]
+fn claiming_a_live_slot_fails() {
+    let mut allocator = melon::entity_id::EntityAllocator::new();
+    let live = allocator.allocate().unwrap();
+    let stale = melon::entity_id::EntityId::from_parts(live.index(), live.generation() + 1);
+    assert!(allocator.conflicts(stale));
+    assert!(!allocator.claim(stale));
+    assert!(allocator.is_alive(live));
+    assert!(allocator.claim(live));
+    allocator.free(live);
+    assert!(!allocator.is_alive(live));
+    assert!(allocator.claim(stale));
+    assert!(allocator.is_alive(stale));
+}
+
+#[test]
+fn claiming_a_freed_generation_fails() {
+    let mut allocator = melon::entity_id::EntityAllocator::new();
+    let despawned = allocator.allocate().unwrap();
+    allocator.free(despawned);
+    assert!(allocator.conflicts(despawned));
+    assert!(!allocator.claim(despawned));
+    assert!(!allocator.is_alive(despawned));
+    let reused = allocator.allocate().unwrap();
+    assert_eq!(reused.index(), despawned.index());
+    assert_ne!(reused, despawned);
+}
+
+#[test]
+fn entity_id_ranges() {
+    let mut world = WorldBuilder::new().with_entity_range(0..100).build();
+    let shard_range = world.reserve_entity_range(50).unwrap();
+    assert_eq!(shard_range, 50..100);
+    let mut shard = WorldBuilder::new().with_entity_range(shard_range).build();
+    let a = world.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    let b = shard.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    assert_ne!(a, b);
+    assert!(b.index() >= 50);
+}
+
+#[test]
+fn spawning_past_the_entity_range_fails() {
+    let mut world = WorldBuilder::new().with_entity_range(0..1).build();
+    world.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    assert!(matches!(
+        world.add_entity().with(Position { x: 0, y: 0 }).try_spawn(),
+        Err(melon::world::WorldError::EntityRangeExhausted)
+    ));
+    assert_eq!(world.number_of_entities(), 1);
+}
+
+#[test]
+fn insert_and_remove_component() {
+    let mut world = melon::default_world::DefaultWorld::get().build();
+    let child = world.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    let parent = world.add_entity().with(Position { x: 1, y: 1 }).spawn();
+    world
+        .insert_component(
+            parent,
+            Children {
+                entities: vec![child],
+            },
+        )
+        .unwrap();
+    //hooks run for inserted components
+    assert_eq!(world.get_component::<Parent>(child).unwrap().entity, parent);
+    world
+        .insert_component(parent, Position { x: 5, y: 5 })
+        .unwrap();
+    assert_eq!(world.get_component::<Position>(parent).unwrap().x, 5);
+    assert_eq!(world.remove_component::<Position>(child).unwrap().x, 0);
+    assert!(world.get_component::<Position>(child).is_none());
+    assert!(world.get_component::<Parent>(child).is_some());
+    assert!(matches!(
+        world.remove_component::<Name>(child),
+        Err(WorldError::ComponentNotFound)
+    ));
+    world.remove_entity(parent);
+    assert!(matches!(
+        world.insert_component(parent, Position { x: 0, y: 0 }),
+        Err(WorldError::EntityNotFound)
+    ));
+}
+
+#[test]
+fn component_ticks() {
+    let mut world = melon::default_world::DefaultWorld::get().build();
+    let child = world.add_entity().with(Position { x: 0, y: 0 }).spawn();
+    let empty = melon::stage::StageBuilder::new().build();
+    world.execute_stage(&empty);
+    world.execute_stage(&empty);
+    let parent = world
+        .add_entity()
+        .with(Children {
+            entities: vec![child],
+        })
+        .spawn();
+    let position = world.get_component_ticks::<Position>(child).unwrap();
+    assert_eq!(position.added, 1);
+    //changes made by hooks are stamped too
+    let parent_ticks = world.get_component_ticks::<Parent>(child).unwrap();
+    assert_eq!(parent_ticks.added, 3);
+    world.execute_stage(&empty);
+    world
+        .insert_component(child, Position { x: 1, y: 0 })
+        .unwrap();
+    let position = world.get_component_ticks::<Position>(child).unwrap();
+    assert_eq!((position.added, position.changed), (1, 4));
+    world.remove_entity(parent);
+    assert!(world.get_component_ticks::<Children>(parent).is_none());
+}